@@ -0,0 +1,220 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use lattice_machines::{BasicLattice, BasicNode, LatMachine, NodeType, ReadNode};
+
+#[derive(Clone)]
+struct Item {
+    id: String,
+    completed: bool,
+}
+
+impl NodeType for Item {
+    fn uuid(&self) -> String {
+        self.id.clone()
+    }
+
+    fn is_completed(&self) -> bool {
+        self.completed
+    }
+}
+
+type Lattice = BasicLattice<BasicNode<Item>>;
+
+// A graph shape as (node count) -> per node dependency lists. Node i only
+// depends on nodes before it. Roots are left incomplete and everything
+// else is completed, so fulfilling the roots cascades through the whole
+// lattice.
+struct Shape {
+    name: &'static str,
+    deps: fn(usize) -> Vec<Vec<usize>>,
+}
+
+fn chain(n: usize) -> Vec<Vec<usize>> {
+    (0..n)
+        .map(|i| if i == 0 { vec![] } else { vec![i - 1] })
+        .collect()
+}
+
+fn fan_out(n: usize) -> Vec<Vec<usize>> {
+    (0..n)
+        .map(|i| if i == 0 { vec![] } else { vec![0] })
+        .collect()
+}
+
+// Layers of 32 nodes, each depending on two nodes of the layer above.
+fn diamond(n: usize) -> Vec<Vec<usize>> {
+    const WIDTH: usize = 32;
+    (0..n)
+        .map(|i| {
+            if i < WIDTH {
+                vec![]
+            } else {
+                let above = i - WIDTH;
+                let base = above - above % WIDTH;
+                vec![above, base + (above + 1) % WIDTH]
+            }
+        })
+        .collect()
+}
+
+// Up to three dependencies picked from the preceding 1000 nodes.
+fn random_dag(n: usize) -> Vec<Vec<usize>> {
+    let mut x: u64 = 0x2545_F491_4F6C_DD1D;
+    let mut next = move || {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        x as usize
+    };
+
+    (0..n)
+        .map(|i| {
+            if i < 8 {
+                return vec![];
+            }
+            let mut deps: Vec<usize> = (0..1 + next() % 3)
+                .map(|_| i - 1 - next() % i.min(1000))
+                .collect();
+            deps.sort_unstable();
+            deps.dedup();
+            deps
+        })
+        .collect()
+}
+
+const SHAPES: &[Shape] = &[
+    Shape {
+        name: "chain",
+        deps: chain,
+    },
+    Shape {
+        name: "fan_out",
+        deps: fan_out,
+    },
+    Shape {
+        name: "diamond",
+        deps: diamond,
+    },
+    Shape {
+        name: "random_dag",
+        deps: random_dag,
+    },
+];
+
+fn sizes() -> Vec<usize> {
+    // The 1M node runs take minutes per shape, opt in explicitly.
+    if std::env::var_os("LATTICE_BENCH_LARGE").is_some() {
+        vec![1_000, 100_000, 1_000_000]
+    } else {
+        vec![1_000, 100_000]
+    }
+}
+
+fn key(i: usize) -> String {
+    format!("n{}", i)
+}
+
+fn nodes(deps: &[Vec<usize>]) -> Vec<BasicNode<Item>> {
+    let mut required_by = vec![Vec::new(); deps.len()];
+    for (i, ds) in deps.iter().enumerate() {
+        for d in ds {
+            required_by[*d].push(key(i));
+        }
+    }
+
+    deps.iter()
+        .zip(required_by)
+        .enumerate()
+        .map(|(i, (ds, rs))| {
+            BasicNode::new(
+                Item {
+                    id: key(i),
+                    completed: !ds.is_empty(),
+                },
+                ds.iter().map(|d| key(*d)).collect(),
+                rs,
+            )
+        })
+        .collect()
+}
+
+fn roots(deps: &[Vec<usize>]) -> Vec<String> {
+    (0..deps.len())
+        .filter(|i| deps[*i].is_empty())
+        .map(key)
+        .collect()
+}
+
+fn bench_append(c: &mut Criterion) {
+    let mut group = c.benchmark_group("append");
+    group.sample_size(10);
+    for shape in SHAPES {
+        for n in sizes() {
+            let deps = (shape.deps)(n);
+            group.bench_with_input(BenchmarkId::new(shape.name, n), &deps, |b, deps| {
+                b.iter_batched(
+                    || nodes(deps),
+                    |v| {
+                        let mut l: Lattice = LatMachine::new();
+                        for node in v {
+                            l.append(node).unwrap();
+                        }
+                        l
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_fulfill_cascade(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fulfill_cascade");
+    group.sample_size(10);
+    for shape in SHAPES {
+        for n in sizes() {
+            let deps = (shape.deps)(n);
+            let roots = roots(&deps);
+            group.bench_with_input(BenchmarkId::new(shape.name, n), &deps, |b, deps| {
+                b.iter_batched(
+                    || BasicLattice::from_node_list(nodes(deps)).unwrap(),
+                    |mut l| {
+                        for r in &roots {
+                            l.fulfill(r.clone()).unwrap();
+                        }
+                        l
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_ready_set(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ready_set");
+    group.sample_size(10);
+    for shape in SHAPES {
+        for n in sizes() {
+            let l = BasicLattice::from_node_list(nodes(&(shape.deps)(n))).unwrap();
+            group.bench_with_input(BenchmarkId::new(shape.name, n), &l, |b, l| {
+                b.iter(|| {
+                    LatMachine::<BasicNode<Item>, Item>::read_pending(l)
+                        .values()
+                        .filter(|t| !t.is_blocked())
+                        .count()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_append,
+    bench_fulfill_cascade,
+    bench_ready_set
+);
+criterion_main!(benches);
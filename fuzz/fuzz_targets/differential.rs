@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../tests/reference/mod.rs"]
+mod reference;
+
+fuzz_target!(|data: &[u8]| {
+    let (spec, ops) = reference::decode(data);
+    if let Err(e) = reference::run(&spec, &ops) {
+        panic!("{}\nspec: {:?}\nops: {:?}", e, spec, ops);
+    }
+});
@@ -0,0 +1,55 @@
+// StructuralChange describes a change to a lattice's shape, as put to a
+// ChangeGate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StructuralChange<'a> {
+    // a new LatNode.
+    Append(&'a str),
+    // the LatNode under the key swapped for, or merged with, a new one,
+    // see DuplicatePolicy.
+    Replace(&'a str),
+    // the LatNode taken out of the lattice, see LatMachine::remove.
+    Remove(&'a str),
+    // requires made to depend on is_required.
+    AddEdge {
+        requires: &'a str,
+        is_required: &'a str,
+    },
+    RemoveEdge {
+        requires: &'a str,
+        is_required: &'a str,
+    },
+    AddReference {
+        from: &'a str,
+        to: &'a str,
+    },
+    RemoveReference {
+        from: &'a str,
+        to: &'a str,
+    },
+    // the LatNode under from re-keyed to to.
+    Rename {
+        from: &'a str,
+        to: &'a str,
+    },
+}
+
+// ApprovalToken is presented with a structural change that needs sign off.
+// What it holds, and what makes it valid, is up to the ChangeGate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApprovalToken(pub String);
+
+// Approver accepts or rejects a token presented for a change.
+pub type Approver = Box<dyn Fn(&StructuralChange, &ApprovalToken) -> bool>;
+
+// ChangeGate decides which structural changes need an ApprovalToken.
+#[derive(Default)]
+pub enum ChangeGate {
+    // changes are only stopped by LatMachine::freeze, and no token gets
+    // past that.
+    #[default]
+    Open,
+    // changes to a frozen lattice, or edges and references touching a
+    // LatNode already in progress (fulfilled, or with some dependency
+    // fulfilled), need a token the function accepts.
+    Approval(Approver),
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::Artifact;
+
+// CacheError is why a FulfillmentCache couldn't be read or written. The
+// lattice treats a failed lookup as a miss and carries on past a failed
+// store.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CacheError {
+    Io(String),
+    // the server answered with this status.
+    Http(u16),
+    // a stored entry couldn't be read back.
+    Corrupt(String),
+}
+
+impl CacheError {
+    // code names the variant, stable as LatticeError::code is.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CacheError::Io(_) => "cache_io",
+            CacheError::Http(_) => "cache_http",
+            CacheError::Corrupt(_) => "cache_corrupt",
+        }
+    }
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CacheError::Io(e) => write!(f, "cache unreachable: {}", e),
+            CacheError::Http(status) => write!(f, "cache server answered {}", status),
+            CacheError::Corrupt(e) => write!(f, "cache entry unreadable: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+impl From<io::Error> for CacheError {
+    fn from(e: io::Error) -> Self {
+        CacheError::Io(e.to_string())
+    }
+}
+
+// CacheEntry is the result of one piece of work: what it produced, and
+// the run ID of the lattice that did it, if it had one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub artifacts: Vec<Artifact>,
+    pub run: Option<String>,
+}
+
+// FulfillmentCache remembers the artifacts of fulfilled work by what went
+// into it: the LatNode's NodeType::fingerprint, then a
+// "producer/name=hash" line for each of its dependencies' artifacts. A
+// lattice with one set, see LatMachine::get_cache, stores every
+// fulfillment in it and fulfills LatNodes it has seen done before as soon
+// as they are appended or unlocked, with Provenance::Cached. DiskCache and
+// HttpCache let separate runs share one.
+pub trait FulfillmentCache {
+    // get returns the entry stored under key, None on a miss.
+    fn get(&self, key: &str) -> Result<Option<CacheEntry>, CacheError>;
+    fn put(&mut self, key: &str, entry: &CacheEntry) -> Result<(), CacheError>;
+}
+
+// BuildCache is a FulfillmentCache held in memory, move it into the next
+// lattice to carry on.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BuildCache {
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl BuildCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<CacheEntry> {
+        self.entries.remove(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl FulfillmentCache for BuildCache {
+    fn get(&self, key: &str) -> Result<Option<CacheEntry>, CacheError> {
+        Ok(self.entries.get(key).cloned())
+    }
+
+    fn put(&mut self, key: &str, entry: &CacheEntry) -> Result<(), CacheError> {
+        self.entries.insert(key.to_string(), entry.clone());
+        Ok(())
+    }
+}
+
+// DiskCache keeps one file per entry under dir, named by a digest of the
+// key, for runs on the same machine or a shared mount.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiskCache {
+    pub dir: PathBuf,
+}
+
+impl DiskCache {
+    // new creates dir if it doesn't exist.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, CacheError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(DiskCache { dir })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(digest(key))
+    }
+}
+
+impl FulfillmentCache for DiskCache {
+    fn get(&self, key: &str) -> Result<Option<CacheEntry>, CacheError> {
+        match fs::read_to_string(self.path(key)) {
+            Ok(entry) => decode(key, &entry),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // put writes to a temporary file and renames it into place, so a
+    // reader never sees half an entry.
+    fn put(&mut self, key: &str, entry: &CacheEntry) -> Result<(), CacheError> {
+        let path = self.path(key);
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&tmp, encode(key, entry))?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+// HttpCache shares entries through a plain HTTP server: GET and PUT on
+// the base URL followed by a digest of the key, 404 for a miss. Requests
+// are HTTP/1.0, one connection each, so bodies are never chunked. Only
+// http:// URLs are supported, put TLS in front with a local proxy. Every
+// call blocks on a round trip: a cascade looks up each LatNode it unlocks
+// as it goes, and stores what it fulfilled once it is over.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpCache {
+    // host:port, from the URL.
+    host: String,
+    // the URL's path, without a trailing slash.
+    path: String,
+    // bounds connecting, and each read and write after.
+    pub timeout: Duration,
+}
+
+impl HttpCache {
+    // new takes the URL entries live under, like
+    // http://cache.internal:8080/lattice. None unless it is an http:// URL.
+    pub fn new(base: &str) -> Option<Self> {
+        let rest = base.strip_prefix("http://")?;
+        let (host, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], rest[i..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        if host.is_empty() {
+            return None;
+        }
+        let host = match host.contains(':') {
+            true => host.to_string(),
+            false => format!("{}:80", host),
+        };
+        Some(HttpCache {
+            host,
+            path: path.to_string(),
+            timeout: Duration::from_secs(5),
+        })
+    }
+
+    // request sends one request and returns the status and body.
+    fn request(&self, method: &str, key: &str, body: &str) -> Result<(u16, Vec<u8>), CacheError> {
+        let mut stream = self.connect()?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let request = format!(
+            "{} {}/{} HTTP/1.0\r\nHost: {}\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            self.path,
+            digest(key),
+            self.host,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes())?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+        let end = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| CacheError::Corrupt("no end to the response headers".to_string()))?;
+        let status = String::from_utf8_lossy(&response[..end])
+            .split(' ')
+            .nth(1)
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| CacheError::Corrupt("no response status".to_string()))?;
+        Ok((status, response.split_off(end + 4)))
+    }
+
+    // connect tries each address host resolves to in turn, giving each
+    // the timeout.
+    fn connect(&self) -> Result<TcpStream, CacheError> {
+        let mut last = None;
+        for addr in self.host.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last = Some(e),
+            }
+        }
+        Err(last
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for host"))
+            .into())
+    }
+}
+
+impl FulfillmentCache for HttpCache {
+    fn get(&self, key: &str) -> Result<Option<CacheEntry>, CacheError> {
+        match self.request("GET", key, "")? {
+            (200, body) => match String::from_utf8(body) {
+                Ok(body) => decode(key, &body),
+                Err(_) => Err(CacheError::Corrupt("entry isn't UTF-8".to_string())),
+            },
+            (404, _) => Ok(None),
+            (status, _) => Err(CacheError::Http(status)),
+        }
+    }
+
+    fn put(&mut self, key: &str, entry: &CacheEntry) -> Result<(), CacheError> {
+        match self.request("PUT", key, &encode(key, entry))? {
+            (200..=299, _) => Ok(()),
+            (status, _) => Err(CacheError::Http(status)),
+        }
+    }
+}
+
+// digest names a key for storage: 64 bit FNV-1a in hex, stable across
+// builds unlike std's hashers. Entries hold their full key, so a
+// collision reads as a miss.
+fn digest(key: &str) -> String {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in key.bytes() {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{:016x}", h)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+fn unescape(s: &str) -> Result<String, CacheError> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            _ => return Err(CacheError::Corrupt(format!("bad escape in {:?}", s))),
+        }
+    }
+    Ok(out)
+}
+
+// encode writes an entry as its key on the first line, its run ID on the
+// second, empty for none (so an empty ID reads back as none), then an
+// artifact a line as name, uri and, if it has one, hash, tab separated.
+fn encode(key: &str, entry: &CacheEntry) -> String {
+    let mut out = escape(key);
+    out.push('\n');
+    out.push_str(&escape(entry.run.as_deref().unwrap_or_default()));
+    for a in &entry.artifacts {
+        out.push('\n');
+        out.push_str(&escape(&a.name));
+        out.push('\t');
+        out.push_str(&escape(&a.uri));
+        if let Some(hash) = &a.hash {
+            out.push('\t');
+            out.push_str(&escape(hash));
+        }
+    }
+    out
+}
+
+// decode reads back what encode wrote, None if the entry is another key's.
+fn decode(key: &str, entry: &str) -> Result<Option<CacheEntry>, CacheError> {
+    let mut lines = entry.split('\n');
+    if unescape(lines.next().unwrap_or_default())? != key {
+        return Ok(None);
+    }
+    let run = match lines.next() {
+        None => return Err(CacheError::Corrupt("no run line".to_string())),
+        Some("") => None,
+        Some(run) => Some(unescape(run)?),
+    };
+
+    let mut artifacts = Vec::new();
+    for line in lines {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let (name, uri, hash) = match fields.as_slice() {
+            [name, uri] => (name, uri, None),
+            [name, uri, hash] => (name, uri, Some(unescape(hash)?)),
+            _ => return Err(CacheError::Corrupt(format!("bad artifact {:?}", line))),
+        };
+        artifacts.push(Artifact {
+            name: unescape(name)?,
+            uri: unescape(uri)?,
+            hash,
+        });
+    }
+    Ok(Some(CacheEntry { artifacts, run }))
+}
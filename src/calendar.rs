@@ -0,0 +1,370 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+
+use crate::{blocked_past, LatMachine, Quorum, WriteNode};
+
+// how far next_working and prev_working look before deciding an owner
+// never works.
+const LOOKAHEAD: i64 = 3660;
+
+// Date is a day on the proleptic Gregorian calendar, stored as days since
+// 1970-01-01.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Date(pub i64);
+
+impl Date {
+    // from_ymd returns None for a day that doesn't exist, like 2023-02-29.
+    pub fn from_ymd(year: i64, month: u32, day: u32) -> Option<Date> {
+        if !(1..=12).contains(&month) || day == 0 {
+            return None;
+        }
+
+        let (m, d) = (month as i64, day as i64);
+        let y = if m <= 2 { year - 1 } else { year };
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let date = Date(era * 146_097 + doe - 719_468);
+        match date.ymd() == (year, month, day) {
+            true => Some(date),
+            false => None,
+        }
+    }
+
+    pub fn ymd(self) -> (i64, u32, u32) {
+        let z = self.0 + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+        (y, m, d)
+    }
+
+    // weekday counts from Monday as 0 to Sunday as 6.
+    pub fn weekday(self) -> usize {
+        // 1970-01-01 was a Thursday.
+        (self.0 + 3).rem_euclid(7) as usize
+    }
+
+    pub fn add_days(self, n: i64) -> Date {
+        Date(self.0 + n)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (y, m, d) = self.ymd();
+        write!(f, "{:04}-{:02}-{:02}", y, m, d)
+    }
+}
+
+// Calendar says which days work gets done on: every day but the weekend
+// and holidays, less any days an owner is away.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Calendar {
+    // by Date::weekday, true for a day off.
+    pub weekend: [bool; 7],
+    holidays: HashMap<Date, ()>,
+    away: HashMap<String, HashMap<Date, ()>>,
+}
+
+// Saturday and Sunday off, no holidays.
+impl Default for Calendar {
+    fn default() -> Self {
+        Calendar {
+            weekend: [false, false, false, false, false, true, true],
+            holidays: HashMap::new(),
+            away: HashMap::new(),
+        }
+    }
+}
+
+impl Calendar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_holiday(&mut self, d: Date) {
+        self.holidays.insert(d, ());
+    }
+
+    // add_away marks owner unavailable on d.
+    pub fn add_away(&mut self, owner: &str, d: Date) {
+        self.away
+            .entry(owner.to_string())
+            .or_default()
+            .insert(d, ());
+    }
+
+    pub fn is_working(&self, owner: Option<&str>, d: Date) -> bool {
+        if self.weekend[d.weekday()] || self.holidays.contains_key(&d) {
+            return false;
+        }
+        match owner.and_then(|o| self.away.get(o)) {
+            Some(away) => !away.contains_key(&d),
+            None => true,
+        }
+    }
+
+    // next_working returns the first working day on or after d, None if
+    // owner has none within ten years.
+    pub fn next_working(&self, owner: Option<&str>, d: Date) -> Option<Date> {
+        (0..LOOKAHEAD)
+            .map(|i| d.add_days(i))
+            .find(|d| self.is_working(owner, *d))
+    }
+
+    // prev_working returns the last working day on or before d, None if
+    // owner has none within ten years.
+    pub fn prev_working(&self, owner: Option<&str>, d: Date) -> Option<Date> {
+        (0..LOOKAHEAD)
+            .map(|i| d.add_days(-i))
+            .find(|d| self.is_working(owner, *d))
+    }
+
+    // finish returns the day work of days working days, begun on the
+    // working day start, is done: start itself for a single day.
+    fn finish(&self, owner: Option<&str>, start: Date, days: u32) -> Option<Date> {
+        let mut d = start;
+        for _ in 1..days {
+            d = self.next_working(owner, d.add_days(1))?;
+        }
+        Some(d)
+    }
+
+    // start is finish backwards, from the working day finish.
+    fn start(&self, owner: Option<&str>, finish: Date, days: u32) -> Option<Date> {
+        let mut d = finish;
+        for _ in 1..days {
+            d = self.prev_working(owner, d.add_days(-1))?;
+        }
+        Some(d)
+    }
+}
+
+// Task is how long a LatNode takes, in working days, and whose calendar it
+// runs on. A Task of 0 days is a milestone, starting and finishing on the
+// same day without taking it up.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Task {
+    pub days: u32,
+    pub owner: Option<String>,
+}
+
+// Window is when a LatNode can run: the earliest it can start and finish,
+// and the latest it can without pushing out the finish date. Both ends are
+// working days the LatNode occupies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Window {
+    pub key: String,
+    pub earliest_start: Date,
+    pub earliest_finish: Date,
+    pub latest_start: Date,
+    pub latest_finish: Date,
+}
+
+impl Window {
+    // critical reports whether any delay pushes out the finish date.
+    pub fn critical(&self) -> bool {
+        self.earliest_start == self.latest_start
+    }
+}
+
+// Schedule is the outcome of LatMachine::schedule_dates.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Schedule {
+    // when the last LatNode finishes, None if nothing could be scheduled.
+    pub finish: Option<Date>,
+    // by key.
+    pub windows: Vec<Window>,
+    // pending LatNodes that couldn't be placed: they wait on a cycle, a key
+    // the lattice doesn't hold, or an owner who never works. Sorted.
+    pub unscheduled: Vec<String>,
+}
+
+// schedule works out every pending LatNode's Window with tasks' durations
+// and owners, starting no earlier than start. Dependents start the working
+// day after everything they depend on finishes, or once enough of a
+// quorum has, soft dependencies never hold them. Latest dates are worked
+// back from finish_by, or from the earliest finish when None.
+pub fn schedule<L, T, U, F>(
+    l: &L,
+    start: Date,
+    calendar: &Calendar,
+    finish_by: Option<Date>,
+    mut tasks: F,
+) -> Schedule
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+    F: FnMut(&str, &T) -> Task,
+{
+    let pending = l.read_pending();
+    let mut waiting_on = HashMap::<&str, Vec<&str>>::new();
+    let mut outstanding = HashMap::<&str, ()>::new();
+    let mut queued = HashMap::<&str, ()>::new();
+    // past holds the keys placed so far.
+    let mut past = HashMap::<String, ()>::new();
+    // (key, first day it may start), waiting to be placed.
+    let mut placing = Vec::new();
+    for (k, t) in pending {
+        outstanding.insert(k, ());
+        if !t.is_blocked() {
+            queued.insert(k, ());
+            placing.push((k.as_str(), start));
+        }
+        for d in t.depends_on().keys() {
+            waiting_on.entry(d).or_default().push(k);
+        }
+    }
+    placing.sort();
+
+    // Forward pass in the order LatNodes finish, so a quorum is met by
+    // the members that finish first. (task, earliest start, earliest
+    // finish) is kept for the backward pass, done_at holds the first day
+    // dependents of a placed LatNode may start.
+    let mut order = Vec::new();
+    let mut early = HashMap::<&str, (Task, Date, Date)>::new();
+    let mut done_at = HashMap::<&str, Date>::new();
+    // (done at, key)
+    let mut finishing = BinaryHeap::new();
+    loop {
+        for (k, from) in placing.drain(..) {
+            let task = tasks(k, &pending[k]);
+            let owner = task.owner.as_deref();
+            let span = match task.days {
+                0 => Some((from, from)),
+                days => calendar
+                    .next_working(owner, from)
+                    .and_then(|s| Some((s, calendar.finish(owner, s, days)?))),
+            };
+            // an owner who never works holds up everything after them.
+            let (es, ef) = match span {
+                None => continue,
+                Some(span) => span,
+            };
+            let next = if task.days == 0 { ef } else { ef.add_days(1) };
+            early.insert(k, (task, es, ef));
+            finishing.push(Reverse((next, k)));
+        }
+
+        let Reverse((next, k)) = match finishing.pop() {
+            None => break,
+            Some(done) => done,
+        };
+        outstanding.remove(k);
+        past.insert(k.to_string(), ());
+        order.push(k);
+        done_at.insert(k, next);
+        for r in waiting_on.get(k).into_iter().flatten() {
+            let t = &pending[*r];
+            if !queued.contains_key(r)
+                && !blocked_past(t.depends_on(), t.fulfilled_by(), t.quorums(), &past)
+            {
+                queued.insert(r, ());
+                placing.push((r, gate(t, &done_at, start)));
+            }
+        }
+    }
+
+    let finish = early.values().map(|(_, _, ef)| *ef).max();
+    let end = match finish_by.or(finish) {
+        None => {
+            return Schedule {
+                unscheduled: sorted(outstanding.keys()),
+                ..Schedule::default()
+            }
+        }
+        Some(end) => end,
+    };
+
+    // Backward pass: a LatNode must finish before the latest start of
+    // everything depending on it, or by end.
+    let mut late = HashMap::<&str, (Date, Date)>::new();
+    let mut windows = Vec::with_capacity(order.len());
+    for k in order.into_iter().rev() {
+        let (task, es, ef) = &early[k];
+        let owner = task.owner.as_deref();
+        let bound = waiting_on
+            .get(k)
+            .into_iter()
+            .flatten()
+            .filter(|r| !pending[**r].is_soft(k))
+            .filter_map(|r| late.get(r).map(|(ls, _)| *ls))
+            .min();
+        let (ls, lf) = match (task.days, bound) {
+            (0, Some(b)) => (b, b),
+            (0, None) => (end, end),
+            (days, b) => {
+                let b = b.map(|b| b.add_days(-1)).unwrap_or(end);
+                let lf = calendar.prev_working(owner, b).unwrap_or(*ef);
+                (calendar.start(owner, lf, days).unwrap_or(*es), lf)
+            }
+        };
+        late.insert(k, (ls, lf));
+        windows.push(Window {
+            key: k.to_string(),
+            earliest_start: *es,
+            earliest_finish: *ef,
+            latest_start: ls,
+            latest_finish: lf,
+        });
+    }
+    windows.sort_by(|a, b| a.key.cmp(&b.key));
+
+    Schedule {
+        finish,
+        windows,
+        unscheduled: sorted(outstanding.keys()),
+    }
+}
+
+// gate is the first day t may start, given the days what it waits on were
+// done: the latest of its plain dependencies and, for each quorum, the
+// day the need-th of its members was. Fulfilled dependencies and soft
+// edges hold it to start.
+fn gate<T, U>(t: &T, done_at: &HashMap<&str, Date>, start: Date) -> Date
+where
+    T: WriteNode<U>,
+{
+    let at = |d: &str| match t.fulfilled_by().contains_key(d) {
+        true => Some(start),
+        false => done_at.get(d).copied(),
+    };
+    let met = |q: &Quorum| {
+        let mut days: Vec<Date> = q.members.iter().filter_map(|m| at(m)).collect();
+        days.sort();
+        match q.need {
+            0 => Some(start),
+            need => days.get(need - 1).copied(),
+        }
+    };
+    t.depends_on()
+        .keys()
+        .filter_map(|d| {
+            let mut quorums = t
+                .quorums()
+                .iter()
+                .filter(|q| q.members.contains(d))
+                .peekable();
+            match quorums.peek() {
+                None => at(d),
+                Some(_) => quorums.filter_map(met).min(),
+            }
+        })
+        .max()
+        .unwrap_or(start)
+        .max(start)
+}
+
+fn sorted<'a>(keys: impl Iterator<Item = &'a &'a str>) -> Vec<String> {
+    let mut v: Vec<String> = keys.map(|k| k.to_string()).collect();
+    v.sort();
+    v
+}
@@ -0,0 +1,708 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use crate::approval::{ApprovalToken, ChangeGate, StructuralChange};
+use crate::error::LatticeError;
+use crate::graph::find_cycles;
+use crate::import::ImportProblem;
+use crate::policy::Mutation;
+use crate::{
+    blocked_past, drop_edge, Artifact, LatMachine, Origin, Placeholder, Provenance, Quorum,
+    WriteNode,
+};
+
+// Changeset collects nodes, edges and fulfillments bound for a lattice so
+// the change can be inspected with preview() before commit() applies it in
+// one go. It is the lattice's transaction: commit applies all of it or,
+// when preview finds a problem, none of it.
+pub struct Changeset<T> {
+    nodes: Vec<T>,
+    edges: Vec<(String, String)>,
+    fulfills: Vec<String>,
+}
+
+// Shadow is a node as check plays the changeset through.
+struct Shadow {
+    pending: bool,
+    // dependencies outstanding, and fulfilled.
+    waiting: HashMap<String, ()>,
+    done: HashMap<String, ()>,
+    quorums: Vec<Quorum>,
+}
+
+impl Shadow {
+    // blocked is ReadNode::is_blocked once the keys in past are fulfilled.
+    fn blocked(&self, past: &HashMap<String, ()>) -> bool {
+        blocked_past(&self.waiting, &self.done, &self.quorums, past)
+    }
+}
+
+// Preview describes what committing a Changeset would do.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Preview {
+    // problems that would stop commit.
+    pub problems: Vec<ImportProblem>,
+    // keys that would be pending with nothing left to wait on, sorted.
+    pub ready: Vec<String>,
+}
+
+impl Preview {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl<T> Default for Changeset<T> {
+    fn default() -> Self {
+        Changeset {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            fulfills: Vec::new(),
+        }
+    }
+}
+
+impl<T> Changeset<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // stage queues a node. Its depends_on, fulfilled_by and required_by
+    // entries are wired up as edges on commit, and its references added,
+    // against staged or existing nodes.
+    pub fn stage(&mut self, t: T) {
+        self.nodes.push(t);
+    }
+
+    // stage_edge queues requires depending on is_required, as
+    // LatMachine::add_requirement.
+    pub fn stage_edge(&mut self, requires: String, is_required: String) {
+        self.edges.push((requires, is_required));
+    }
+
+    // stage_fulfill queues key to be fulfilled, as LatMachine::fulfill,
+    // once every staged node and edge is in. Fulfillments run in the order
+    // staged.
+    pub fn stage_fulfill(&mut self, key: String) {
+        self.fulfills.push(key);
+    }
+
+    pub fn nodes(&self) -> &[T] {
+        &self.nodes
+    }
+
+    pub fn edges(&self) -> &[(String, String)] {
+        &self.edges
+    }
+
+    pub fn fulfills(&self) -> &[String] {
+        &self.fulfills
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.edges.is_empty() && self.fulfills.is_empty()
+    }
+
+    // all_edges returns the edges declared by staged nodes followed by the
+    // staged edges, as (requires, is_required) without repeats.
+    fn all_edges<U>(&self) -> Vec<(String, String)>
+    where
+        T: WriteNode<U>,
+    {
+        let mut out = Vec::new();
+        for t in &self.nodes {
+            let key = t.uuid();
+            let mut deps: Vec<&String> = t
+                .depends_on()
+                .keys()
+                .chain(t.fulfilled_by().keys())
+                .collect();
+            deps.sort();
+            out.extend(deps.into_iter().map(|d| (key.clone(), d.clone())));
+
+            let mut reqs: Vec<&String> = t.required_by().keys().collect();
+            reqs.sort();
+            out.extend(reqs.into_iter().map(|r| (r.clone(), key.clone())));
+        }
+        out.extend(self.edges.iter().cloned());
+
+        let mut seen = HashMap::<(String, String), ()>::new();
+        out.retain(|e| seen.insert(e.clone(), ()).is_none());
+        out
+    }
+
+    // resolved_edges is all_edges with any of l's aliases replaced by the
+    // keys they stand for.
+    fn resolved_edges<L, U>(&self, l: &L) -> Vec<(String, String)>
+    where
+        L: LatMachine<T, U>,
+        T: WriteNode<U>,
+    {
+        let mut edges = self.all_edges();
+        if !l.read_aliases().is_empty() {
+            for (requires, is_required) in edges.iter_mut() {
+                *requires = l.canonical(requires).to_string();
+                *is_required = l.canonical(is_required).to_string();
+            }
+            let mut seen = HashMap::<(String, String), ()>::new();
+            edges.retain(|e| seen.insert(e.clone(), ()).is_none());
+        }
+        edges
+    }
+
+    // preview validates the changeset against l and works out which nodes
+    // would be ready afterwards, without touching l.
+    pub fn preview<L, U>(&self, l: &L) -> Preview
+    where
+        L: LatMachine<T, U>,
+        T: WriteNode<U>,
+    {
+        self.check(l, None)
+    }
+
+    // check is preview, presenting token for any change l's ChangeGate
+    // wants approved.
+    fn check<L, U>(&self, l: &L, token: Option<&ApprovalToken>) -> Preview
+    where
+        L: LatMachine<T, U>,
+        T: WriteNode<U>,
+    {
+        let mut preview = Preview::default();
+
+        let mut frozen = false;
+        for t in &self.nodes {
+            match l.approve(&StructuralChange::Append(&t.uuid()), token) {
+                Ok(()) => {}
+                Err(LatticeError::Denied(reason)) => {
+                    preview.problems.push(ImportProblem::Denied(reason))
+                }
+                Err(_) => frozen = true,
+            }
+        }
+        // Staged nodes that are already completed will be in progress by
+        // the time their edges are added.
+        let done: HashMap<String, ()> = self
+            .nodes
+            .iter()
+            .filter(|t| t.is_completed())
+            .map(|t| (t.uuid(), ()))
+            .collect();
+        for (requires, is_required) in self.resolved_edges(l) {
+            let change = StructuralChange::AddEdge {
+                requires: &requires,
+                is_required: &is_required,
+            };
+            if let Err(LatticeError::Denied(reason)) = l.approve(&change, token) {
+                preview.problems.push(ImportProblem::Denied(reason));
+                continue;
+            }
+            let approved = match l.read_change_gate() {
+                ChangeGate::Approval(f)
+                    if done.contains_key(&requires) || done.contains_key(&is_required) =>
+                {
+                    token.is_some_and(|token| f(&change, token))
+                }
+                _ => true,
+            };
+            if approved && l.approve(&change, token).is_ok() {
+                continue;
+            }
+            if l.is_frozen() {
+                frozen = true;
+            } else {
+                preview.problems.push(ImportProblem::NeedsApproval {
+                    requires: requires.clone(),
+                    is_required: is_required.clone(),
+                });
+            }
+        }
+        if frozen {
+            preview.problems.push(ImportProblem::Frozen);
+        }
+
+        let size = self.nodes.len() + self.all_edges().len();
+        if let Some(limit) = l.read_limits().max_mutation {
+            if size > limit {
+                preview
+                    .problems
+                    .push(ImportProblem::TooLarge { size, limit });
+            }
+        }
+
+        let mut shadow = HashMap::<String, Shadow>::new();
+        let mut completed = HashMap::<String, ()>::new();
+        for (k, t) in l.read_pending().iter().chain(l.read_fulfilled().iter()) {
+            let s = Shadow {
+                pending: l.read_pending().contains_key(k),
+                waiting: t.depends_on().clone(),
+                done: t.fulfilled_by().clone(),
+                quorums: t.quorums().to_vec(),
+            };
+            shadow.insert(k.clone(), s);
+        }
+        for t in l.read_pending().values().chain(self.nodes.iter()) {
+            if t.is_completed() && t.skipped().is_none() && !l.read_failed().contains_key(&t.uuid())
+            {
+                completed.insert(t.uuid(), ());
+            }
+        }
+
+        for t in &self.nodes {
+            match shadow.entry(t.uuid()) {
+                Entry::Occupied(e) => {
+                    let key = e.key().clone();
+                    preview.problems.push(ImportProblem::DuplicateKey(key));
+                }
+                // staged nodes are appended without edges, as commit does.
+                Entry::Vacant(e) => {
+                    e.insert(Shadow {
+                        pending: !t.is_completed(),
+                        waiting: HashMap::new(),
+                        done: HashMap::new(),
+                        quorums: t.quorums().to_vec(),
+                    });
+                }
+            }
+        }
+
+        let edges = self.resolved_edges(l);
+        for (requires, is_required) in &edges {
+            for (node, reference) in &[(is_required, requires), (requires, is_required)] {
+                if !shadow.contains_key(*reference) {
+                    preview.problems.push(ImportProblem::UnknownReference {
+                        node: node.to_string(),
+                        reference: reference.to_string(),
+                    });
+                }
+            }
+        }
+
+        for t in &self.nodes {
+            let key = t.uuid();
+            let mut refs: Vec<&String> = t.quorums().iter().flat_map(|q| &q.members).collect();
+            if l.read_limits().strict_keys {
+                refs.extend(t.references().keys());
+            }
+            refs.sort();
+            refs.dedup();
+            for r in refs {
+                if !shadow.contains_key(l.canonical(r)) {
+                    preview.problems.push(ImportProblem::UnknownReference {
+                        node: key.clone(),
+                        reference: r.clone(),
+                    });
+                }
+            }
+        }
+
+        // Cycles over the combined graph, existing edges included.
+        let mut keys: Vec<&String> = shadow.keys().collect();
+        keys.sort();
+        let index: HashMap<&String, usize> =
+            keys.iter().enumerate().map(|(i, k)| (*k, i)).collect();
+        let mut deps = vec![Vec::new(); keys.len()];
+        let existing = l.read_pending().iter().chain(l.read_fulfilled().iter());
+        for (k, t) in existing {
+            for d in t.depends_on().keys().chain(t.fulfilled_by().keys()) {
+                if let Some(d) = index.get(d) {
+                    deps[index[k]].push(*d);
+                }
+            }
+        }
+        for (requires, is_required) in &edges {
+            if let (Some(r), Some(i)) = (index.get(requires), index.get(is_required)) {
+                deps[*r].push(*i);
+            }
+        }
+        for d in deps.iter_mut() {
+            d.sort_unstable();
+            d.dedup();
+        }
+        for (cycle, _) in find_cycles(0..keys.len(), &deps) {
+            let cycle = cycle.into_iter().map(|i| keys[i].clone()).collect();
+            preview.problems.push(ImportProblem::Cycle(cycle));
+        }
+
+        // Replay the edges with add_requirement's rules.
+        let none = HashMap::new();
+        for (requires, is_required) in &edges {
+            let still_required = match shadow.get(is_required) {
+                None => continue,
+                Some(s) => s.pending,
+            };
+            if let Some(s) = shadow.get_mut(requires) {
+                match still_required {
+                    true => {
+                        s.waiting.insert(is_required.clone(), ());
+                        s.pending |= s.blocked(&none);
+                    }
+                    false => {
+                        s.done.insert(is_required.clone(), ());
+                    }
+                }
+            }
+        }
+
+        self.replay_fulfills(l, &mut shadow, &completed, &mut preview);
+
+        preview.ready = shadow
+            .into_iter()
+            .filter(|(_, s)| s.pending && !s.blocked(&none))
+            .map(|(k, _)| k)
+            .collect();
+        preview.ready.sort();
+        preview
+    }
+
+    // commit applies the changeset to l if preview finds no problems. On
+    // failure l is untouched and the changeset is handed back with the
+    // preview explaining why. Should applying it fail regardless, what was
+    // applied is rolled back and the preview holds ImportProblem::Failed,
+    // though entries already put in l's FulfillmentCache stay there.
+    pub fn commit<L, U>(self, l: &mut L) -> Result<(), (Self, Preview)>
+    where
+        L: LatMachine<T, U>,
+        T: WriteNode<U>,
+    {
+        self.commit_with(l, None)
+    }
+
+    // commit_approved is commit, presenting token for any change l's
+    // ChangeGate wants approved.
+    pub fn commit_approved<L, U>(
+        self,
+        l: &mut L,
+        token: &ApprovalToken,
+    ) -> Result<(), (Self, Preview)>
+    where
+        L: LatMachine<T, U>,
+        T: WriteNode<U>,
+    {
+        self.commit_with(l, Some(token))
+    }
+
+    fn commit_with<L, U>(
+        self,
+        l: &mut L,
+        token: Option<&ApprovalToken>,
+    ) -> Result<(), (Self, Preview)>
+    where
+        L: LatMachine<T, U>,
+        T: WriteNode<U>,
+    {
+        let preview = self.check(l, token);
+        if !preview.is_clean() {
+            return Err((self, preview));
+        }
+
+        let edges = self.resolved_edges(l);
+        let log = l.read_fulfillment_log().len();
+        // what a rollback puts back on pending LatNodes a cascade reaches.
+        let artifacts: HashMap<String, Vec<Artifact>> = l
+            .read_pending()
+            .iter()
+            .filter(|(_, t)| !t.artifacts().is_empty())
+            .map(|(k, t)| (k.clone(), t.artifacts().to_vec()))
+            .collect();
+        let Changeset {
+            nodes,
+            edges: staged_edges,
+            fulfills,
+        } = self;
+
+        // nodes go in bare, their edges and references follow once every
+        // one is in.
+        let mut taken = Vec::<Taken>::with_capacity(nodes.len());
+        let mut added = Vec::<(String, String, bool)>::new();
+        let mut nodes = nodes.into_iter();
+        let mut failed = None;
+        for mut t in nodes.by_ref() {
+            let saved = Taken::from(l, &mut t);
+            match l.try_append_approved(t, token) {
+                Ok(_) => taken.push(saved),
+                Err((t, e)) => {
+                    failed = Some((e, Some(saved.restore(t))));
+                    break;
+                }
+            }
+        }
+        if failed.is_none() {
+            for saved in &taken {
+                if let Some(t) = l.get_pending().get_mut(&saved.key) {
+                    *t.get_references() = saved.references.clone();
+                } else if let Some(t) = l.get_fulfilled().get_mut(&saved.key) {
+                    *t.get_references() = saved.references.clone();
+                }
+            }
+        }
+
+        for (requires, is_required) in edges {
+            if failed.is_some() {
+                break;
+            }
+            let held = l.read_node(&requires).is_some_and(|t| {
+                t.depends_on().contains_key(&is_required)
+                    || t.fulfilled_by().contains_key(&is_required)
+            });
+            let was_fulfilled = l.read_fulfilled().contains_key(&requires);
+            match l.add_requirement_approved(requires.clone(), is_required.clone(), token) {
+                Ok(()) if !held => added.push((requires, is_required, was_fulfilled)),
+                Ok(()) => {}
+                Err(e) => failed = Some((e, None)),
+            }
+        }
+
+        for key in &fulfills {
+            if failed.is_some() {
+                break;
+            }
+            if let Err(e) = l.fulfill(key.clone()) {
+                failed = Some((e, None));
+            }
+        }
+
+        let (e, t) = match failed {
+            None => return Ok(()),
+            Some(f) => f,
+        };
+        let mut back = roll_back(l, log, artifacts, added, taken);
+        back.extend(t);
+        back.extend(nodes);
+        let preview = Preview {
+            problems: vec![ImportProblem::Failed(e)],
+            ready: Vec::new(),
+        };
+        let changes = Changeset {
+            nodes: back,
+            edges: staged_edges,
+            fulfills,
+        };
+        Err((changes, preview))
+    }
+
+    // replay_fulfills plays the staged fulfillments against shadow, as
+    // check leaves it, reporting any that LatMachine::fulfill would refuse:
+    // keys that aren't pending by then, still wait on a dependency, are
+    // skipped or have failed, or cascades past l's Limits.
+    fn replay_fulfills<L, U>(
+        &self,
+        l: &L,
+        shadow: &mut HashMap<String, Shadow>,
+        completed: &HashMap<String, ()>,
+        preview: &mut Preview,
+    ) where
+        L: LatMachine<T, U>,
+        T: WriteNode<U>,
+    {
+        if self.fulfills.is_empty() {
+            return;
+        }
+
+        // key -> the keys waiting on it. Waits only ever end from here on.
+        let mut dependents = HashMap::<String, Vec<String>>::new();
+        for (k, s) in shadow.iter() {
+            for d in s.waiting.keys() {
+                dependents.entry(d.clone()).or_default().push(k.clone());
+            }
+        }
+
+        let limits = l.read_limits();
+        for key in &self.fulfills {
+            let key = l.canonical(key).to_string();
+            let fail = match shadow.get(&key) {
+                None => Some(LatticeError::UnknownKey(key.clone())),
+                Some(s) if !s.pending => Some(LatticeError::AlreadyFulfilled(key.clone())),
+                Some(s) if s.blocked(&HashMap::new()) => Some(LatticeError::Blocked(key.clone())),
+                Some(_) => match l.read_pending().get(&key) {
+                    Some(_) if l.read_failed().contains_key(&key) => {
+                        Some(LatticeError::Failed(key.clone()))
+                    }
+                    Some(t) if t.skipped().is_some() => Some(LatticeError::Skipped(key.clone())),
+                    _ => None,
+                },
+            };
+            let fail = fail.or_else(|| l.permit(&Mutation::Fulfill(&key)).err());
+            if let Some(e) = fail {
+                preview.problems.push(ImportProblem::FulfillFails(e));
+                continue;
+            }
+
+            // The cascade, as LatMachine::cascade_extent walks it.
+            let mut past = HashMap::<String, ()>::new();
+            let mut queued = HashMap::<&str, ()>::from([(key.as_str(), ())]);
+            let mut cascade = Vec::new();
+            let mut stack = vec![(key.as_str(), 1)];
+            let mut depth = 0;
+            while let Some((k, d)) = stack.pop() {
+                cascade.push(k.to_string());
+                past.insert(k.to_string(), ());
+                depth = depth.max(d);
+                for r in dependents.get(k).into_iter().flatten() {
+                    let s = &shadow[r];
+                    if !s.pending || !s.waiting.contains_key(k) || queued.contains_key(r.as_str()) {
+                        continue;
+                    }
+                    if !s.blocked(&past) && completed.contains_key(r) {
+                        queued.insert(r, ());
+                        stack.push((r, d + 1));
+                    }
+                }
+            }
+
+            let size = cascade.len();
+            if limits.max_cascade_size.is_some_and(|max| size > max)
+                || limits.max_cascade_depth.is_some_and(|max| depth > max)
+            {
+                preview
+                    .problems
+                    .push(ImportProblem::FulfillFails(LatticeError::CascadeLimit {
+                        key,
+                        size,
+                        depth,
+                    }));
+                continue;
+            }
+
+            for k in cascade {
+                shadow.get_mut(&k).unwrap().pending = false;
+                for r in dependents.get(&k).into_iter().flatten() {
+                    let s = shadow.get_mut(r).unwrap();
+                    if s.pending && s.waiting.remove(&k).is_some() {
+                        s.done.insert(k.clone(), ());
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Taken is what commit takes off a staged node before appending it, and
+// what the lattice held under its key, to put back on rolling back.
+struct Taken {
+    key: String,
+    depends_on: HashMap<String, ()>,
+    required_by: HashMap<String, ()>,
+    fulfilled_by: HashMap<String, ()>,
+    references: HashMap<String, ()>,
+    artifacts: Vec<Artifact>,
+    provenance: Option<Provenance>,
+    origin: Option<Origin>,
+    placeholder: Option<Placeholder>,
+}
+
+impl Taken {
+    fn from<L, T, U>(l: &L, t: &mut T) -> Self
+    where
+        L: LatMachine<T, U>,
+        T: WriteNode<U>,
+    {
+        let key = t.uuid();
+        Taken {
+            placeholder: l.read_placeholders().get(&key).cloned(),
+            key,
+            depends_on: std::mem::take(t.get_depends_on()),
+            required_by: std::mem::take(t.get_required_by()),
+            fulfilled_by: std::mem::take(t.get_fulfilled_by()),
+            references: std::mem::take(t.get_references()),
+            artifacts: t.artifacts().to_vec(),
+            provenance: t.provenance().cloned(),
+            origin: t.origin().cloned(),
+        }
+    }
+
+    // restore puts t back as it was staged.
+    fn restore<T, U>(self, t: T) -> T
+    where
+        T: WriteNode<U>,
+    {
+        let mut t = t;
+        *t.get_depends_on() = self.depends_on;
+        *t.get_required_by() = self.required_by;
+        *t.get_fulfilled_by() = self.fulfilled_by;
+        *t.get_references() = self.references;
+        *t.get_artifacts() = self.artifacts;
+        t.set_provenance(self.provenance);
+        t.set_origin(self.origin);
+        t
+    }
+}
+
+// roll_back undoes a commit that failed part way: fulfillments logged
+// since log, newest first, their LatNodes getting back the artifacts they
+// held before, then the edges added, then the nodes taken, which are
+// handed back as they were staged.
+fn roll_back<L, T, U>(
+    l: &mut L,
+    log: usize,
+    mut artifacts: HashMap<String, Vec<Artifact>>,
+    added: Vec<(String, String, bool)>,
+    taken: Vec<Taken>,
+) -> Vec<T>
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+{
+    let undone = l.get_fulfillment_log().split_off(log);
+    for f in undone.iter().rev() {
+        let mut t = match l.get_fulfilled().remove(&f.key) {
+            None => continue,
+            Some(t) => t,
+        };
+        t.set_provenance(None);
+        t.set_origin(None);
+        *t.get_artifacts() = artifacts.remove(&f.key).unwrap_or_default();
+        let dependents: Vec<String> = t.required_by().keys().cloned().collect();
+        l.get_pending().insert(f.key.clone(), t);
+        for r in dependents {
+            depend_unfulfilled(l, &r, &f.key);
+        }
+    }
+
+    for (requires, is_required, was_fulfilled) in added.into_iter().rev() {
+        drop_edge(l, &requires, &is_required);
+        if was_fulfilled {
+            if let Some(t) = l.get_pending().remove(&requires) {
+                l.get_fulfilled().insert(requires, t);
+            }
+        }
+    }
+
+    let mut out = Vec::with_capacity(taken.len());
+    for saved in taken.into_iter().rev() {
+        let t = match l.get_pending().remove(&saved.key) {
+            Some(t) => t,
+            None => match l.get_fulfilled().remove(&saved.key) {
+                Some(t) => t,
+                None => continue,
+            },
+        };
+        l.get_handles().release(&saved.key);
+        if let Some(p) = &saved.placeholder {
+            for r in p.required_by.keys() {
+                depend_unfulfilled(l, r, &saved.key);
+            }
+            l.get_placeholders().insert(saved.key.clone(), p.clone());
+        }
+        out.push(saved.restore(t));
+    }
+    out.reverse();
+    out
+}
+
+// depend_unfulfilled sets key waiting on dep again, if it holds dep as
+// fulfilled.
+fn depend_unfulfilled<L, T, U>(l: &mut L, key: &str, dep: &str)
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+{
+    let t = match l.get_pending().get_mut(key) {
+        Some(t) => Some(t),
+        None => l.get_fulfilled().get_mut(key),
+    };
+    if let Some(t) = t {
+        if t.fulfilled_by().contains_key(dep) {
+            let _ = t.depend_unfulfilled(dep.to_string());
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+use crate::error::LatticeError;
+use crate::{LatMachine, WriteNode};
+
+// DenseLattice is a compact, index based copy of a lattice's state for
+// small, hot lattices (up to roughly 10k nodes). Every key is mapped to an
+// index and each node's relations are stored as rows of a bit matrix, so
+// readiness checks and cascades become word-wide bit operations instead
+// of string hashing. Memory grows with the square of the node count.
+pub struct DenseLattice {
+    keys: Vec<String>,
+    index: HashMap<String, usize>,
+    // number of u64 words per bitset row.
+    words: usize,
+    // row i holds the nodes i is still waiting on.
+    depends_on: Vec<u64>,
+    // row i holds the nodes that require i.
+    required_by: Vec<u64>,
+    completed: Vec<u64>,
+    fulfilled: Vec<u64>,
+    // by node, its Quorums, see WriteNode::add_quorum.
+    quorums: Vec<Vec<DenseQuorum>>,
+}
+
+// DenseQuorum is a Quorum with its members as a bitset row.
+struct DenseQuorum {
+    members: Vec<u64>,
+    need: usize,
+    // members fulfilled before the snapshot that the lattice no longer
+    // holds.
+    outside: usize,
+}
+
+impl DenseQuorum {
+    fn is_met(&self, fulfilled: &[u64]) -> bool {
+        let done: u32 = self
+            .members
+            .iter()
+            .zip(fulfilled)
+            .map(|(m, f)| (m & f).count_ones())
+            .sum();
+        done as usize + self.outside >= self.need
+    }
+}
+
+fn bit(i: usize) -> (usize, u64) {
+    (i / 64, 1 << (i % 64))
+}
+
+impl DenseLattice {
+    // from_lattice snapshots a LatMachine's topology and state, Quorums
+    // and soft edges included. Fails if any relation points at a key the
+    // lattice doesn't hold.
+    pub fn from_lattice<L, T, U>(l: &L) -> Result<Self, LatticeError>
+    where
+        L: LatMachine<T, U>,
+        T: WriteNode<U>,
+    {
+        let mut keys = Vec::new();
+        let mut index = HashMap::new();
+        for k in l.read_pending().keys().chain(l.read_fulfilled().keys()) {
+            index.insert(k.clone(), keys.len());
+            keys.push(k.clone());
+        }
+
+        let n = keys.len();
+        let words = n.div_ceil(64);
+        let mut s = DenseLattice {
+            keys,
+            index,
+            words,
+            depends_on: vec![0; n * words],
+            required_by: vec![0; n * words],
+            completed: vec![0; words],
+            fulfilled: vec![0; words],
+            quorums: (0..n).map(|_| Vec::new()).collect(),
+        };
+
+        let nodes = l
+            .read_pending()
+            .values()
+            .map(|t| (t, false))
+            .chain(l.read_fulfilled().values().map(|t| (t, true)));
+
+        for (t, is_fulfilled) in nodes {
+            let i = s.index[&t.uuid()];
+            let (w, b) = bit(i);
+            if t.is_completed() {
+                s.completed[w] |= b;
+            }
+            if is_fulfilled {
+                s.fulfilled[w] |= b;
+            }
+
+            for d in t.depends_on().keys() {
+                let j = match s.index.get(d) {
+                    None => return Err(LatticeError::UnknownKey(d.clone())),
+                    Some(j) => *j,
+                };
+                let (w, b) = bit(j);
+                s.depends_on[i * words + w] |= b;
+            }
+
+            for r in t.required_by().keys() {
+                let j = match s.index.get(r) {
+                    None => return Err(LatticeError::UnknownKey(r.clone())),
+                    Some(j) => *j,
+                };
+                let (w, b) = bit(j);
+                s.required_by[i * words + w] |= b;
+            }
+
+            for q in t.quorums() {
+                let mut dq = DenseQuorum {
+                    members: vec![0; words],
+                    need: q.need,
+                    outside: 0,
+                };
+                for m in &q.members {
+                    match s.index.get(m) {
+                        Some(j) => {
+                            let (w, b) = bit(*j);
+                            dq.members[w] |= b;
+                        }
+                        None if t.fulfilled_by().contains_key(m) => dq.outside += 1,
+                        None => return Err(LatticeError::UnknownKey(m.clone())),
+                    }
+                }
+                s.quorums[i].push(dq);
+            }
+        }
+
+        Ok(s)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn index_of(&self, key: &str) -> Option<usize> {
+        self.index.get(key).copied()
+    }
+
+    pub fn key_of(&self, i: usize) -> Option<&str> {
+        self.keys.get(i).map(|k| k.as_str())
+    }
+
+    fn row(&self, i: usize) -> std::ops::Range<usize> {
+        i * self.words..(i + 1) * self.words
+    }
+
+    fn test(set: &[u64], i: usize) -> bool {
+        let (w, b) = bit(i);
+        set[w] & b != 0
+    }
+
+    pub fn is_fulfilled(&self, i: usize) -> bool {
+        Self::test(&self.fulfilled, i)
+    }
+
+    pub fn is_completed(&self, i: usize) -> bool {
+        Self::test(&self.completed, i)
+    }
+
+    // returns whether i is pending with no outstanding dependencies.
+    pub fn is_ready(&self, i: usize) -> bool {
+        !self.is_blocked(i) && !self.is_fulfilled(i)
+    }
+
+    // is_blocked mirrors ReadNode::is_blocked: i waits on a dependency
+    // unless every one outstanding is in a met Quorum. Soft edges are
+    // Quorums that need none.
+    pub fn is_blocked(&self, i: usize) -> bool {
+        let waiting = &self.depends_on[self.row(i)];
+        let met: Vec<&DenseQuorum> = self.quorums[i]
+            .iter()
+            .filter(|q| q.is_met(&self.fulfilled))
+            .collect();
+        waiting.iter().enumerate().any(|(w, bits)| {
+            let spared = met.iter().fold(0, |acc, q| acc | q.members[w]);
+            bits & !spared != 0
+        })
+    }
+
+    // returns the indices of every ready node.
+    pub fn ready(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len()).filter(move |i| self.is_ready(*i))
+    }
+
+    pub fn set_completed(&mut self, i: usize, completed: bool) {
+        let (w, b) = bit(i);
+        if completed {
+            self.completed[w] |= b;
+        } else {
+            self.completed[w] &= !b;
+        }
+    }
+
+    // fulfill mirrors LatMachine::fulfill: i is marked fulfilled, cleared
+    // from its dependents, and any dependent left completed with nothing
+    // outstanding is fulfilled in turn. Blocked while i still waits on a
+    // dependency.
+    pub fn fulfill(&mut self, i: usize) -> Result<(), LatticeError> {
+        if i >= self.len() {
+            return Err(LatticeError::UnknownKey(i.to_string()));
+        }
+        if self.is_fulfilled(i) {
+            return Err(LatticeError::AlreadyFulfilled(self.keys[i].clone()));
+        }
+        if self.is_blocked(i) {
+            return Err(LatticeError::Blocked(self.keys[i].clone()));
+        }
+
+        // nodes are marked fulfilled as they are queued so a node reached
+        // through several dependencies is only visited once.
+        let (w, b) = bit(i);
+        self.fulfilled[w] |= b;
+        let mut stack = vec![i];
+        while let Some(i) = stack.pop() {
+            let (w, b) = bit(i);
+            let r = self.row(i);
+            for word in 0..self.words {
+                let mut set = self.required_by[r.start + word];
+                while set != 0 {
+                    let j = word * 64 + set.trailing_zeros() as usize;
+                    set &= set - 1;
+
+                    let jr = self.row(j).start;
+                    self.depends_on[jr + w] &= !b;
+                    if self.is_ready(j) && self.is_completed(j) {
+                        let (jw, jb) = bit(j);
+                        self.fulfilled[jw] |= jb;
+                        stack.push(j);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::{LatMachine, NodeState, WriteNode};
+
+// StateChange is a LatNode both lattices hold standing differently in
+// each.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateChange {
+    pub key: String,
+    pub before: NodeState,
+    pub after: NodeState,
+}
+
+// LatticeDiff is what changed going from one lattice to another, see
+// LatMachine::diff. Edges are (requires, is_required), every list sorted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatticeDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub edges_added: Vec<(String, String)>,
+    pub edges_removed: Vec<(String, String)>,
+    pub state_changes: Vec<StateChange>,
+}
+
+impl LatticeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.edges_added.is_empty()
+            && self.edges_removed.is_empty()
+            && self.state_changes.is_empty()
+    }
+
+    // newly_failed lists the LatNodes both lattices hold that have failed
+    // since, sorted.
+    pub fn newly_failed(&self) -> Vec<&str> {
+        self.state_changes
+            .iter()
+            .filter(|c| c.after == NodeState::Failed)
+            .map(|c| c.key.as_str())
+            .collect()
+    }
+}
+
+// nodes lists every key l holds a LatNode under.
+fn nodes<L, T, U>(l: &L) -> HashMap<String, ()>
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+{
+    l.read_pending()
+        .keys()
+        .chain(l.read_fulfilled().keys())
+        .map(|k| (k.clone(), ()))
+        .collect()
+}
+
+// edges lists every dependency l's LatNodes hold, done or not.
+fn edges<L, T, U>(l: &L) -> HashMap<(String, String), ()>
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+{
+    let mut out = HashMap::new();
+    for (k, t) in l.read_pending().iter().chain(l.read_fulfilled().iter()) {
+        for d in t.depends_on().keys().chain(t.fulfilled_by().keys()) {
+            out.insert((k.clone(), d.clone()), ());
+        }
+    }
+    out
+}
+
+// only lists the keys of a that b lacks, sorted.
+fn only<K: Clone + Ord + Hash>(a: &HashMap<K, ()>, b: &HashMap<K, ()>) -> Vec<K> {
+    let mut v: Vec<K> = a.keys().filter(|k| !b.contains_key(*k)).cloned().collect();
+    v.sort();
+    v
+}
+
+// diff compares before with after by key. Placeholders aren't LatNodes,
+// only the edges to them count.
+pub fn diff<L, M, T, U>(before: &L, after: &M) -> LatticeDiff
+where
+    L: LatMachine<T, U>,
+    M: LatMachine<T, U>,
+    T: WriteNode<U>,
+{
+    let (was, now) = (nodes(before), nodes(after));
+    let (was_edges, now_edges) = (edges(before), edges(after));
+
+    let mut state_changes: Vec<StateChange> = was
+        .keys()
+        .filter_map(|k| {
+            let (b, a) = (before.node_state(k)?, after.node_state(k)?);
+            match b == a {
+                true => None,
+                false => Some(StateChange {
+                    key: k.clone(),
+                    before: b,
+                    after: a,
+                }),
+            }
+        })
+        .collect();
+    state_changes.sort_by(|a, b| a.key.cmp(&b.key));
+
+    LatticeDiff {
+        added: only(&now, &was),
+        removed: only(&was, &now),
+        edges_added: only(&now_edges, &was_edges),
+        edges_removed: only(&was_edges, &now_edges),
+        state_changes,
+    }
+}
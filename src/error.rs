@@ -0,0 +1,142 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::handle::{NodeHandle, StaleHandle};
+
+// LatticeError is why a lattice operation changed nothing, or stopped
+// partway (see Cancelled).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LatticeError {
+    // the lattice holds no LatNode (or alias) with this key.
+    UnknownKey(String),
+    // node doesn't wait on dep, or has no record of dep being fulfilled
+    // when reverting.
+    DependencyNotFound {
+        node: String,
+        dep: String,
+    },
+    // from holds no reference to to.
+    ReferenceNotFound {
+        from: String,
+        to: String,
+    },
+    // the LatNode has already left pending.
+    AlreadyFulfilled(String),
+    // the LatNode hasn't been fulfilled yet.
+    NotFulfilled(String),
+    // the LatNode is skipped with SkipPolicy::Blocks, see unskip.
+    Skipped(String),
+    // the LatNode isn't skipped with SkipPolicy::Blocks.
+    NotSkipped(String),
+    // the LatNode has failed, see recover.
+    Failed(String),
+    // the LatNode still waits on a dependency, see force_fulfill.
+    Blocked(String),
+    // the LatNode hasn't failed.
+    NotFailed(String),
+    // the key is already taken by a LatNode, placeholder or alias.
+    KeyTaken(String),
+    // the edge would close this dependency cycle, each key depending on
+    // the next and the last on the first.
+    Cycle(Vec<String>),
+    // the LatNode would depend on itself.
+    SelfDependency(String),
+    // an update to the LatNode under key carries data for uuid instead.
+    KeyMismatch {
+        key: String,
+        uuid: String,
+    },
+    // fulfilling key would cascade through more LatNodes, or deeper, than
+    // the lattice's Limits allow. size and depth are the would be extent.
+    CascadeLimit {
+        key: String,
+        size: usize,
+        depth: usize,
+    },
+    // the LatNode behind the handle has been removed.
+    StaleHandle(NodeHandle),
+    // the lattice is frozen and no accepted token was presented.
+    Frozen,
+    // the ChangeGate wants an accepted token for this change.
+    NeedsApproval,
+    // a CancelToken or step callback stopped the operation; whatever was
+    // done before it stopped stays done.
+    Cancelled,
+    // the lattice's MutationPolicy refused the change, for this reason.
+    Denied(String),
+}
+
+impl LatticeError {
+    // code names the variant for callers that can't match on it, HTTP and
+    // FFI layers say. Codes are never changed or reused once released, new
+    // variants get new ones.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LatticeError::UnknownKey(_) => "unknown_key",
+            LatticeError::DependencyNotFound { .. } => "dependency_not_found",
+            LatticeError::ReferenceNotFound { .. } => "reference_not_found",
+            LatticeError::AlreadyFulfilled(_) => "already_fulfilled",
+            LatticeError::NotFulfilled(_) => "not_fulfilled",
+            LatticeError::Skipped(_) => "skipped",
+            LatticeError::NotSkipped(_) => "not_skipped",
+            LatticeError::Failed(_) => "failed",
+            LatticeError::NotFailed(_) => "not_failed",
+            LatticeError::Blocked(_) => "blocked",
+            LatticeError::KeyTaken(_) => "key_taken",
+            LatticeError::Cycle(_) => "cycle",
+            LatticeError::SelfDependency(_) => "self_dependency",
+            LatticeError::KeyMismatch { .. } => "key_mismatch",
+            LatticeError::CascadeLimit { .. } => "cascade_limit",
+            LatticeError::StaleHandle(_) => "stale_handle",
+            LatticeError::Frozen => "frozen",
+            LatticeError::NeedsApproval => "needs_approval",
+            LatticeError::Cancelled => "cancelled",
+            LatticeError::Denied(_) => "denied",
+        }
+    }
+}
+
+impl fmt::Display for LatticeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LatticeError::UnknownKey(k) => write!(f, "unknown key {}", k),
+            LatticeError::DependencyNotFound { node, dep } => {
+                write!(f, "{} has no dependency on {}", node, dep)
+            }
+            LatticeError::ReferenceNotFound { from, to } => {
+                write!(f, "{} holds no reference to {}", from, to)
+            }
+            LatticeError::AlreadyFulfilled(k) => write!(f, "{} is already fulfilled", k),
+            LatticeError::NotFulfilled(k) => write!(f, "{} is not fulfilled", k),
+            LatticeError::Skipped(k) => write!(f, "{} is skipped", k),
+            LatticeError::NotSkipped(k) => write!(f, "{} is not skipped", k),
+            LatticeError::Failed(k) => write!(f, "{} has failed", k),
+            LatticeError::NotFailed(k) => write!(f, "{} has not failed", k),
+            LatticeError::Blocked(k) => write!(f, "{} is still waiting on a dependency", k),
+            LatticeError::KeyTaken(k) => write!(f, "key {} is already taken", k),
+            LatticeError::Cycle(keys) => write!(f, "dependency cycle {}", keys.join(" -> ")),
+            LatticeError::SelfDependency(k) => write!(f, "{} cannot depend on itself", k),
+            LatticeError::KeyMismatch { key, uuid } => {
+                write!(f, "update for {} carries data for {}", key, uuid)
+            }
+            LatticeError::CascadeLimit { key, size, depth } => write!(
+                f,
+                "fulfilling {} would cascade through {} LatNodes, {} deep, past the lattice's limits",
+                key, size, depth
+            ),
+            LatticeError::StaleHandle(h) => write!(f, "stale handle {:?}", h),
+            LatticeError::Frozen => write!(f, "lattice is frozen"),
+            LatticeError::NeedsApproval => write!(f, "change needs approval"),
+            LatticeError::Cancelled => write!(f, "cancelled"),
+            LatticeError::Denied(reason) => write!(f, "denied: {}", reason),
+        }
+    }
+}
+
+impl Error for LatticeError {}
+
+impl From<StaleHandle> for LatticeError {
+    fn from(e: StaleHandle) -> Self {
+        LatticeError::StaleHandle(e.0)
+    }
+}
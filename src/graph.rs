@@ -0,0 +1,50 @@
+// Index based graph helpers shared by the validating parts of the crate.
+
+const WHITE: u8 = 0;
+const GREY: u8 = 1;
+const BLACK: u8 = 2;
+
+// find_cycles runs a depth first search over deps (node -> the nodes it
+// depends on), starting from nodes in the given order. Every edge back
+// onto the current path closes a cycle, which is returned along with
+// that edge. Removing all returned edges leaves the graph acyclic.
+pub(crate) fn find_cycles(
+    order: impl IntoIterator<Item = usize>,
+    deps: &[Vec<usize>],
+) -> Vec<(Vec<usize>, (usize, usize))> {
+    let mut color = vec![WHITE; deps.len()];
+    let mut found = Vec::new();
+    for start in order {
+        if color[start] != WHITE {
+            continue;
+        }
+
+        color[start] = GREY;
+        let mut path = vec![(start, 0)];
+        while let Some((u, next)) = path.last_mut() {
+            let u = *u;
+            if *next == deps[u].len() {
+                color[u] = BLACK;
+                path.pop();
+                continue;
+            }
+
+            let w = deps[u][*next];
+            *next += 1;
+            match color[w] {
+                WHITE => {
+                    color[w] = GREY;
+                    path.push((w, 0));
+                }
+                GREY => {
+                    let from = path.iter().position(|(x, _)| *x == w).unwrap_or(0);
+                    let cycle = path[from..].iter().map(|(x, _)| *x).collect();
+                    found.push((cycle, (u, w)));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    found
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::mem::size_of;
+
+use crate::heap_size::HeapSize;
+
+// NodeHandle is a cheap, copyable stand in for a LatNode's key, returned
+// by LatMachine::append. Resolving one indexes the lattice's HandleTable
+// instead of hashing or cloning a key. Handles are generational: once the
+// LatNode is removed its handle goes stale rather than addressing
+// whichever LatNode reuses the slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeHandle {
+    index: usize,
+    generation: u32,
+}
+
+// StaleHandle is returned when a handle's LatNode has been removed or
+// re-keyed since the handle was issued.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StaleHandle(pub NodeHandle);
+
+impl StaleHandle {
+    // code is the same as LatticeError::StaleHandle's.
+    pub fn code(&self) -> &'static str {
+        "stale_handle"
+    }
+}
+
+impl fmt::Display for StaleHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "stale handle {:?}", self.0)
+    }
+}
+
+impl Error for StaleHandle {}
+
+// HandleTable interns the key of every LatNode in a lattice, giving each a
+// single NodeHandle until it is released.
+#[derive(Clone, Debug, Default)]
+pub struct HandleTable {
+    // (key, generation), the key is None while the slot is free.
+    slots: Vec<(Option<String>, u32)>,
+    free: Vec<usize>,
+    handles: HashMap<String, NodeHandle>,
+}
+
+impl HandleTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // intern returns key's handle, allocating one if key has none.
+    pub fn intern(&mut self, key: &str) -> NodeHandle {
+        if let Some(h) = self.handles.get(key) {
+            return *h;
+        }
+
+        let h = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.0 = Some(key.to_string());
+                NodeHandle {
+                    index,
+                    generation: slot.1,
+                }
+            }
+            None => {
+                self.slots.push((Some(key.to_string()), 0));
+                NodeHandle {
+                    index: self.slots.len() - 1,
+                    generation: 0,
+                }
+            }
+        };
+        self.handles.insert(key.to_string(), h);
+        h
+    }
+
+    // release retires key's handle so it reports stale from now on, and
+    // frees its slot for reuse. Returns the retired handle, if any.
+    pub fn release(&mut self, key: &str) -> Option<NodeHandle> {
+        let h = self.handles.remove(key)?;
+        let slot = &mut self.slots[h.index];
+        slot.0 = None;
+        slot.1 = slot.1.wrapping_add(1);
+        self.free.push(h.index);
+        Some(h)
+    }
+
+    // handle returns key's handle, None if it has none.
+    pub fn handle(&self, key: &str) -> Option<NodeHandle> {
+        self.handles.get(key).copied()
+    }
+
+    // key returns the key h was handed out for, or StaleHandle if it has
+    // been released since (or came from another table).
+    pub fn key(&self, h: NodeHandle) -> Result<&str, StaleHandle> {
+        match self.slots.get(h.index) {
+            Some((Some(k), generation)) if *generation == h.generation => Ok(k.as_str()),
+            _ => Err(StaleHandle(h)),
+        }
+    }
+
+    // len returns the number of live handles.
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+}
+
+impl HeapSize for HandleTable {
+    fn heap_size(&self) -> usize {
+        self.slots.capacity() * size_of::<(Option<String>, u32)>()
+            + self.slots.iter().map(|(k, _)| k.heap_size()).sum::<usize>()
+            + self.free.heap_size()
+            + self.handles.capacity() * (size_of::<(String, NodeHandle)>() + 1)
+            + self.handles.keys().map(|k| k.heap_size()).sum::<usize>()
+    }
+}
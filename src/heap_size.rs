@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+
+// HeapSize estimates the bytes a value owns on the heap, not counting
+// its own inline size. Implement it for base data types to have them
+// included in LatMachine::memory_footprint.
+pub trait HeapSize {
+    fn heap_size(&self) -> usize;
+}
+
+macro_rules! no_heap {
+    ($($t:ty),*) => {
+        $(impl HeapSize for $t {
+            fn heap_size(&self) -> usize {
+                0
+            }
+        })*
+    };
+}
+
+no_heap!(
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    ()
+);
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(|t| t.heap_size()).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, |t| t.heap_size())
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<T> {
+    fn heap_size(&self) -> usize {
+        size_of::<T>() + (**self).heap_size()
+    }
+}
+
+impl<V: HeapSize> HeapSize for HashMap<String, V> {
+    fn heap_size(&self) -> usize {
+        // one control byte per bucket on top of the slot itself.
+        self.capacity() * (size_of::<(String, V)>() + 1)
+            + self
+                .iter()
+                .map(|(k, v)| k.heap_size() + v.heap_size())
+                .sum::<usize>()
+    }
+}
+
+// shrink_map shrinks m to fit and returns an estimate of the bytes freed.
+pub(crate) fn shrink_map<V>(m: &mut HashMap<String, V>) -> usize {
+    let before = m.capacity();
+    m.shrink_to_fit();
+    (before - m.capacity()) * (size_of::<(String, V)>() + 1)
+}
+
+// MemoryFootprint is an estimate of a lattice's heap usage, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryFootprint {
+    // heap owned by node payloads.
+    pub nodes: usize,
+    // the depends_on, required_by, fulfilled_by and references maps of
+    // every node.
+    pub edges: usize,
+    // the lattice's own key -> node tables, including inline node storage,
+    // and its handle table.
+    pub indexes: usize,
+}
+
+impl MemoryFootprint {
+    pub fn total(&self) -> usize {
+        self.nodes + self.edges + self.indexes
+    }
+}
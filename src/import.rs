@@ -0,0 +1,154 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use crate::error::LatticeError;
+use crate::graph::find_cycles;
+use crate::{BasicLattice, BasicNode, NodeType, ReadNode, WriteNode};
+
+// A single problem found while importing a node list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImportProblem {
+    // the key appeared more than once, only the first node is kept.
+    DuplicateKey(String),
+    // node lists reference as a dependency or dependent, but no node in
+    // the input has that key.
+    UnknownReference {
+        node: String,
+        reference: String,
+    },
+    // the keys form a dependency cycle, each depending on the next and
+    // the last depending on the first.
+    Cycle(Vec<String>),
+    // the change adds more nodes and edges than Limits::max_mutation allows.
+    TooLarge {
+        size: usize,
+        limit: usize,
+    },
+    // the lattice is frozen, see LatMachine::freeze.
+    Frozen,
+    // the lattice's ChangeGate wants this edge approved.
+    NeedsApproval {
+        requires: String,
+        is_required: String,
+    },
+    // a staged fulfillment would fail with this error.
+    FulfillFails(LatticeError),
+    // the lattice's MutationPolicy refused a staged node or edge, for
+    // this reason.
+    Denied(String),
+    // applying a change preview passed failed with this error anyway, and
+    // what had been applied was rolled back.
+    Failed(LatticeError),
+}
+
+// ImportReport collects every problem found in an input rather than
+// stopping at the first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub problems: Vec<ImportProblem>,
+}
+
+impl ImportReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl<T: NodeType> BasicLattice<BasicNode<T>> {
+    // from_node_list_checked builds a lattice only if the input is free of
+    // duplicates, unknown references and cycles, otherwise returns every
+    // problem found.
+    pub fn from_node_list_checked(
+        v: Vec<BasicNode<T>>,
+    ) -> Result<BasicLattice<BasicNode<T>>, ImportReport> {
+        let (nodes, report) = salvage(v);
+        if report.is_clean() {
+            // salvage leaves no duplicates.
+            Ok(BasicLattice::from_node_list(nodes).unwrap())
+        } else {
+            Err(report)
+        }
+    }
+
+    // from_node_list_lenient drops whatever from_node_list_checked would
+    // reject (later duplicates, edges to unknown keys, one edge of each
+    // cycle) and returns the salvaged lattice alongside the report.
+    pub fn from_node_list_lenient(
+        v: Vec<BasicNode<T>>,
+    ) -> (BasicLattice<BasicNode<T>>, ImportReport) {
+        let (nodes, report) = salvage(v);
+        (BasicLattice::from_node_list(nodes).unwrap(), report)
+    }
+}
+
+// salvage removes every offending node and edge from v, reporting each.
+fn salvage<T: NodeType>(v: Vec<BasicNode<T>>) -> (Vec<BasicNode<T>>, ImportReport) {
+    let mut report = ImportReport::default();
+    let mut index = HashMap::<String, usize>::new();
+    let mut nodes = Vec::<BasicNode<T>>::with_capacity(v.len());
+
+    for node in v {
+        match index.entry(node.uuid()) {
+            Entry::Occupied(e) => {
+                report
+                    .problems
+                    .push(ImportProblem::DuplicateKey(e.key().clone()));
+            }
+            Entry::Vacant(e) => {
+                e.insert(nodes.len());
+                nodes.push(node);
+            }
+        }
+    }
+
+    crate::backfill(&mut nodes);
+
+    for node in nodes.iter_mut() {
+        let key = node.uuid();
+        let mut unknown: Vec<String> = node
+            .depends_on()
+            .keys()
+            .chain(node.required_by().keys())
+            .filter(|k| !index.contains_key(*k))
+            .cloned()
+            .collect();
+        unknown.sort();
+        unknown.dedup();
+
+        for reference in unknown {
+            node.get_depends_on().remove(&reference);
+            node.get_required_by().remove(&reference);
+            report.problems.push(ImportProblem::UnknownReference {
+                node: key.clone(),
+                reference,
+            });
+        }
+    }
+
+    // Search for cycles in key order so reports are stable.
+    let mut keys: Vec<&String> = index.keys().collect();
+    keys.sort();
+    let deps: Vec<Vec<usize>> = nodes
+        .iter()
+        .map(|node| {
+            let mut d: Vec<usize> = node.depends_on().keys().map(|k| index[k]).collect();
+            d.sort_by_key(|i| nodes[*i].uuid());
+            d
+        })
+        .collect();
+
+    let mut back_edges = Vec::<(usize, usize)>::new();
+    for (cycle, edge) in find_cycles(keys.iter().map(|k| index[*k]), &deps) {
+        let cycle = cycle.into_iter().map(|x| nodes[x].uuid()).collect();
+        report.problems.push(ImportProblem::Cycle(cycle));
+        back_edges.push(edge);
+    }
+
+    for (u, w) in back_edges {
+        let (uk, wk) = (nodes[u].uuid(), nodes[w].uuid());
+        nodes[u].get_depends_on().remove(&wk);
+        nodes[w].get_required_by().remove(&uk);
+    }
+
+    (nodes, report)
+}
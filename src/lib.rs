@@ -1,4 +1,80 @@
-use std::collections::HashMap;
+use std::collections::{hash_map, HashMap, VecDeque};
+use std::iter::Chain;
+use std::mem::size_of;
+use std::ops::Index;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod approval;
+pub mod cache;
+pub mod calendar;
+pub mod changeset;
+pub mod dense;
+pub mod diff;
+pub mod error;
+mod graph;
+pub mod handle;
+pub mod heap_size;
+pub mod import;
+pub mod policy;
+pub mod registry;
+pub mod render;
+#[cfg(feature = "report")]
+pub mod report;
+pub mod run;
+pub mod simulate;
+pub mod template;
+#[cfg(feature = "tracker")]
+pub mod tracker;
+
+use approval::{ApprovalToken, ChangeGate, StructuralChange};
+use cache::{CacheEntry, FulfillmentCache};
+use calendar::{Calendar, Date, Schedule, Task};
+use changeset::{Changeset, Preview};
+use diff::LatticeDiff;
+use error::LatticeError;
+use graph::find_cycles;
+use handle::{HandleTable, NodeHandle, StaleHandle};
+use heap_size::{shrink_map, HeapSize, MemoryFootprint};
+use import::ImportProblem;
+use policy::{Mutation, MutationPolicy};
+use run::{RunError, RunReport, Timeout};
+use simulate::{Sensitivity, Simulation};
+
+// cascade_log emits one structured record per cascade decision when the
+// `log` feature is enabled, and compiles to nothing otherwise.
+#[cfg(feature = "log")]
+macro_rules! cascade_log {
+    ($($arg:tt)*) => {
+        log::debug!(target: "lattice_machines::cascade", $($arg)*)
+    };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! cascade_log {
+    ($($arg:tt)*) => {};
+}
+
+// cache_log is cascade_log for FulfillmentCache hits.
+#[cfg(feature = "log")]
+macro_rules! cache_log {
+    ($($arg:tt)*) => {
+        log::debug!(target: "lattice_machines::cache", $($arg)*)
+    };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! cache_log {
+    ($($arg:tt)*) => {};
+}
+
+// blocked_on renders what t waits on as a stable, comma separated list.
+#[cfg(feature = "log")]
+fn blocked_on<T, N: ReadNode<T>>(t: &N) -> String {
+    let v: Vec<&str> = t.waiting_on().into_iter().map(|k| k.as_str()).collect();
+    v.join(",")
+}
 
 // The base data and measure of "completed-ness" required to be
 // useable inside of a lattice machine
@@ -7,6 +83,93 @@ pub trait NodeType {
     fn uuid(&self) -> String;
     // returns if the base data type is completed
     fn is_completed(&self) -> bool;
+    // returns the cost of completing the base data type, used for
+    // budget accounting across the lattice. Free by default.
+    fn cost(&self) -> u64 {
+        0
+    }
+    // overrides the lattice's FulfillPolicy for this base data when it
+    // reports completed: Some(true) always auto-fulfills, Some(false)
+    // always waits for an explicit fulfill, None defers to the lattice.
+    fn auto_fulfill(&self) -> Option<bool> {
+        None
+    }
+    // returns the swimlane (team, tag, namespace...) diagrams cluster
+    // this base data under. None leaves it ungrouped.
+    fn group(&self) -> Option<String> {
+        None
+    }
+    // returns how long the base data's work may run before
+    // run_to_completion_timed abandons it. No limit by default.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    // returns what the base data's work produced, recorded on the LatNode
+    // as its artifacts when it is fulfilled. Nothing by default.
+    fn outputs(&self) -> Vec<Artifact> {
+        Vec::new()
+    }
+    // returns a hash of the base data's work itself, its command and
+    // configuration say, for a FulfillmentCache to recognise it by along
+    // with its inputs. None, the default, never caches.
+    fn fingerprint(&self) -> Option<String> {
+        None
+    }
+}
+
+// Provenance records how a LatNode came to be fulfilled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Provenance {
+    // fulfilled by a direct call, e.g. LatMachine::fulfill.
+    Explicit,
+    // fulfilled automatically when the keyed LatNode's fulfillment
+    // cascaded into it.
+    Cascade(String),
+    // fulfilled as soon as it was ready, its work found in the lattice's
+    // FulfillmentCache.
+    Cached,
+}
+
+// Origin is where a LatNode's fulfillment came from, so an artifact can be
+// traced to the run that produced it: that run's ID, None if it had none,
+// see LatMachine::get_run_id, and how the result reached this lattice.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Origin {
+    pub run: Option<String>,
+    pub kind: OriginKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OriginKind {
+    // this lattice fulfilled it.
+    Local,
+    // found in the lattice's FulfillmentCache, run is whoever stored it.
+    Cache,
+    // taken over from another copy of the lattice by sync_from.
+    Replica,
+}
+
+// Artifact is something a LatNode's work produced, and where to find it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Artifact {
+    // unique per LatNode.
+    pub name: String,
+    pub uri: String,
+    // a content hash in whatever form the producer uses, if it took one.
+    pub hash: Option<String>,
+}
+
+// SkipPolicy is how a skipped LatNode's dependents treat it, see
+// LatMachine::skip.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SkipPolicy {
+    // as fulfilled: the LatNode moves to fulfilled and its dependents
+    // carry on without it.
+    Satisfies,
+    // as never done: the LatNode stays pending, Skipped, and no fulfill
+    // or cascade will move it, holding its dependents back for good.
+    Blocks,
 }
 
 pub trait ReadNode<T>: NodeType {
@@ -14,6 +177,21 @@ pub trait ReadNode<T>: NodeType {
     // and list of relations it depends on.
     fn new(base_data: T, depends_on: Vec<String>, required_by: Vec<String>) -> Self;
 
+    // try_new is new that refuses a LatNode listing itself as a
+    // dependency or dependent, which could never activate.
+    fn try_new(
+        base_data: T,
+        depends_on: Vec<String>,
+        required_by: Vec<String>,
+    ) -> Result<Self, LatticeError>
+    where
+        Self: Sized,
+    {
+        let s = Self::new(base_data, depends_on, required_by);
+        self_edge(&s)?;
+        Ok(s)
+    }
+
     // returns a hashmap where keys are the LatNode who this LatNode depends on being
     // complete before becoming active and a bool indicating whether that LatNode
     // is active or not
@@ -22,16 +200,111 @@ pub trait ReadNode<T>: NodeType {
     fn required_by(&self) -> &HashMap<String, ()>;
     // returns a hashmap where keys are the completed LatNodes this depended on.
     fn fulfilled_by(&self) -> &HashMap<String, ()>;
+    // returns a hashmap where keys are LatNodes this one is related to.
+    // References never block activation or take part in cascades.
+    fn references(&self) -> &HashMap<String, ()>;
+    // returns how this LatNode was fulfilled, None if the lattice never
+    // fulfilled it (still pending, or appended already fulfilled).
+    fn provenance(&self) -> Option<&Provenance>;
+    // returns where this LatNode's fulfillment came from, None when
+    // provenance is.
+    fn origin(&self) -> Option<&Origin>;
+    // returns how this LatNode was skipped, None if it wasn't.
+    fn skipped(&self) -> Option<SkipPolicy>;
+    // returns what this LatNode produced, see NodeType::outputs and
+    // LatMachine::attach_artifact.
+    fn artifacts(&self) -> &[Artifact];
+    // returns the groups of dependencies this LatNode needs only some of,
+    // see WriteNode::add_quorum.
+    fn quorums(&self) -> &[Quorum];
     // returns whether this LatNode should be active.
     fn is_active(&self) -> bool {
-        !(!self.is_completed() && self.depends_on().is_empty())
+        self.is_completed() || self.is_blocked()
+    }
+
+    // is_blocked reports whether this LatNode still waits on a
+    // dependency. Keys in depends_on don't count once a Quorum they belong
+    // to is met.
+    fn is_blocked(&self) -> bool {
+        self.depends_on().keys().any(|d| !spared(self, d))
+    }
+
+    // waiting_on lists the dependencies holding this LatNode back, sorted.
+    fn waiting_on(&self) -> Vec<&String> {
+        let mut v: Vec<&String> = self
+            .depends_on()
+            .keys()
+            .filter(|d| !spared(self, d))
+            .collect();
+        v.sort();
+        v
     }
 
     fn is_pending(&self) -> bool {
-        !self.depends_on().is_empty() || !self.is_completed()
+        self.is_blocked() || !self.is_completed()
+    }
+
+    // is_soft reports whether key is a dependency this LatNode never waits
+    // on, see WriteNode::add_soft_depends_on.
+    fn is_soft(&self, key: &str) -> bool {
+        self.quorums()
+            .iter()
+            .any(|q| q.need == 0 && q.members.iter().any(|m| m == key))
+    }
+}
+
+// spared reports whether t no longer waits on d: d is in a Quorum of t's
+// that is met.
+fn spared<T, N: ReadNode<T> + ?Sized>(t: &N, d: &str) -> bool {
+    t.quorums()
+        .iter()
+        .any(|q| q.members.iter().any(|m| m == d) && q.is_met(t.fulfilled_by()))
+}
+
+// blocked_past is ReadNode::is_blocked for a LatNode waiting on waiting
+// with done fulfilled, once the keys in past are fulfilled as well, for
+// working out a cascade without running it.
+pub(crate) fn blocked_past(
+    waiting: &HashMap<String, ()>,
+    done: &HashMap<String, ()>,
+    quorums: &[Quorum],
+    past: &HashMap<String, ()>,
+) -> bool {
+    let met = |q: &Quorum| {
+        let n = q
+            .members
+            .iter()
+            .filter(|m| done.contains_key(*m) || past.contains_key(*m))
+            .count();
+        n >= q.need
+    };
+    waiting
+        .keys()
+        .filter(|d| !past.contains_key(*d))
+        .any(|d| !quorums.iter().any(|q| q.members.contains(d) && met(q)))
+}
+
+// Quorum is a group of dependencies a LatNode needs only need of, see
+// WriteNode::add_quorum. Its members are ordinary edges besides.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Quorum {
+    pub members: Vec<String>,
+    pub need: usize,
+}
+
+impl Quorum {
+    // is_met reports whether need of the members are in fulfilled_by.
+    pub fn is_met(&self, fulfilled_by: &HashMap<String, ()>) -> bool {
+        let done = self
+            .members
+            .iter()
+            .filter(|m| fulfilled_by.contains_key(*m))
+            .count();
+        done >= self.need
     }
 }
 
+#[derive(Clone)]
 pub struct BasicNode<T>
 where
     T: NodeType,
@@ -40,6 +313,12 @@ where
     depends_on: HashMap<String, ()>,
     required_by: HashMap<String, ()>,
     fulfilled_by: HashMap<String, ()>,
+    references: HashMap<String, ()>,
+    provenance: Option<Provenance>,
+    origin: Option<Origin>,
+    skipped: Option<SkipPolicy>,
+    artifacts: Vec<Artifact>,
+    quorums: Vec<Quorum>,
 }
 
 impl<T: NodeType> NodeType for BasicNode<T> {
@@ -50,6 +329,42 @@ impl<T: NodeType> NodeType for BasicNode<T> {
     fn is_completed(&self) -> bool {
         self.base_data.is_completed()
     }
+
+    fn cost(&self) -> u64 {
+        self.base_data.cost()
+    }
+
+    fn auto_fulfill(&self) -> Option<bool> {
+        self.base_data.auto_fulfill()
+    }
+
+    fn group(&self) -> Option<String> {
+        self.base_data.group()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.base_data.timeout()
+    }
+
+    fn outputs(&self) -> Vec<Artifact> {
+        self.base_data.outputs()
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        self.base_data.fingerprint()
+    }
+}
+
+impl<T: NodeType> BasicNode<T> {
+    // returns the base data this node wraps.
+    pub fn base_data(&self) -> &T {
+        &self.base_data
+    }
+
+    // into_inner gives up the node, returning the base data it wrapped.
+    pub fn into_inner(self) -> T {
+        self.base_data
+    }
 }
 
 impl<T: NodeType> ReadNode<T> for BasicNode<T> {
@@ -59,6 +374,12 @@ impl<T: NodeType> ReadNode<T> for BasicNode<T> {
             depends_on: HashMap::new(),
             fulfilled_by: HashMap::new(),
             required_by: HashMap::new(),
+            references: HashMap::new(),
+            provenance: None,
+            origin: None,
+            skipped: None,
+            artifacts: Vec::new(),
+            quorums: Vec::new(),
         };
 
         for v in depends_on {
@@ -83,6 +404,38 @@ impl<T: NodeType> ReadNode<T> for BasicNode<T> {
     fn fulfilled_by(&self) -> &HashMap<String, ()> {
         &self.fulfilled_by
     }
+
+    fn references(&self) -> &HashMap<String, ()> {
+        &self.references
+    }
+
+    fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+
+    fn origin(&self) -> Option<&Origin> {
+        self.origin.as_ref()
+    }
+
+    fn skipped(&self) -> Option<SkipPolicy> {
+        self.skipped
+    }
+
+    fn artifacts(&self) -> &[Artifact] {
+        &self.artifacts
+    }
+
+    fn quorums(&self) -> &[Quorum] {
+        &self.quorums
+    }
+}
+
+// Only the payload is counted, LatMachine::memory_footprint accounts for
+// the relation maps as edges.
+impl<T: NodeType + HeapSize> HeapSize for BasicNode<T> {
+    fn heap_size(&self) -> usize {
+        self.base_data.heap_size()
+    }
 }
 
 pub trait WriteNode<T>: ReadNode<T> {
@@ -97,8 +450,11 @@ pub trait WriteNode<T>: ReadNode<T> {
     // returns a hashmap where keys are the completed LatNodes this
     // depended on
     fn get_fulfilled_by(&mut self) -> &mut HashMap<String, ()>;
-    // returns a hashmap where keys are the completed LatNodes this
-    // depended on.
+    // returns a mutable reference to a hashmap where keys are the
+    // LatNodes this one is related to
+    fn get_references(&mut self) -> &mut HashMap<String, ()>;
+    fn get_artifacts(&mut self) -> &mut Vec<Artifact>;
+    fn get_quorums(&mut self) -> &mut Vec<Quorum>;
 
     // add_depends_on takes a LatNode's UUID and adds it to the relations
     fn add_depends_on(&mut self, key: String) {
@@ -110,9 +466,47 @@ pub trait WriteNode<T>: ReadNode<T> {
         self.get_required_by().insert(key, ());
     }
 
-    fn depend_fulfilled(&mut self, key: String) -> Result<(), ()> {
+    // add_reference takes a LatNode's UUID and adds it to the references
+    fn add_reference(&mut self, key: String) {
+        self.get_references().insert(key, ());
+    }
+
+    // add_quorum makes this LatNode wait on need of members rather than
+    // all of them: each key is added to the relations, and once need of
+    // them are fulfilled the rest stop blocking. No members is ignored.
+    fn add_quorum(&mut self, members: Vec<String>, need: usize) {
+        let mut members = members;
+        members.sort();
+        members.dedup();
+        if members.is_empty() {
+            return;
+        }
+        for k in &members {
+            if !self.fulfilled_by().contains_key(k) {
+                self.add_depends_on(k.clone());
+            }
+        }
+        self.get_quorums().push(Quorum { members, need });
+    }
+
+    // add_any_of is add_quorum needing one of group.
+    fn add_any_of(&mut self, group: Vec<String>) {
+        self.add_quorum(group, 1);
+    }
+
+    // add_soft_depends_on adds key to the relations without ever blocking
+    // on it, a Quorum needing none. The cascade still moves it to
+    // fulfilled_by once it is fulfilled.
+    fn add_soft_depends_on(&mut self, key: String) {
+        self.add_quorum(vec![key], 0);
+    }
+
+    fn depend_fulfilled(&mut self, key: String) -> Result<(), LatticeError> {
         match self.get_depends_on().remove(&key) {
-            None => Err(()),
+            None => Err(LatticeError::DependencyNotFound {
+                node: self.uuid(),
+                dep: key,
+            }),
             Some(_) => {
                 self.get_fulfilled_by().insert(key, ());
                 Ok(())
@@ -120,7 +514,39 @@ pub trait WriteNode<T>: ReadNode<T> {
         }
     }
 
-    fn update(&mut self, t: T) -> Result<(), ()>;
+    // depend_unfulfilled reverses depend_fulfilled, Err if key wasn't
+    // recorded as fulfilled.
+    fn depend_unfulfilled(&mut self, key: String) -> Result<(), LatticeError> {
+        match self.get_fulfilled_by().remove(&key) {
+            None => Err(LatticeError::DependencyNotFound {
+                node: self.uuid(),
+                dep: key,
+            }),
+            Some(_) => {
+                self.get_depends_on().insert(key, ());
+                Ok(())
+            }
+        }
+    }
+
+    // update replaces the LatNode's data with t. The lattice files the
+    // LatNode, and every edge to it, under its uuid, so data with another
+    // uuid must be refused with KeyMismatch and the LatNode left as it was.
+    fn update(&mut self, t: T) -> Result<(), LatticeError>;
+
+    // records how this LatNode was fulfilled.
+    fn set_provenance(&mut self, p: Option<Provenance>);
+
+    // records where this LatNode's fulfillment came from.
+    fn set_origin(&mut self, o: Option<Origin>);
+
+    // set_data swaps in t, returning the data it replaces. Unlike update
+    // it doesn't check t's uuid, the caller re-keys the LatNode, see
+    // LatMachine::rename.
+    fn set_data(&mut self, t: T) -> T;
+
+    // marks this LatNode skipped, or not with None.
+    fn set_skipped(&mut self, s: Option<SkipPolicy>);
 }
 
 impl<T: NodeType> WriteNode<T> for BasicNode<T> {
@@ -136,259 +562,3811 @@ impl<T: NodeType> WriteNode<T> for BasicNode<T> {
         &mut self.fulfilled_by
     }
 
-    fn update(&mut self, t: T) -> Result<(), ()> {
-        self.base_data = t;
-        Ok(())
+    fn get_references(&mut self) -> &mut HashMap<String, ()> {
+        &mut self.references
     }
-}
 
-pub trait LatMachine<T, U>
-where
-    T: WriteNode<U>,
-{
-    // default impl?
-    fn new() -> Self;
+    fn get_artifacts(&mut self) -> &mut Vec<Artifact> {
+        &mut self.artifacts
+    }
 
-    // If embedding as a LatNode, then we get this for free.
-    fn is_completed(&self) -> bool {
-        self.read_pending().is_empty()
+    fn get_quorums(&mut self) -> &mut Vec<Quorum> {
+        &mut self.quorums
     }
 
-    fn read_pending(&self) -> &HashMap<String, T>;
-    fn read_fulfilled(&self) -> &HashMap<String, T>;
+    fn update(&mut self, t: T) -> Result<(), LatticeError> {
+        let (key, uuid) = (self.uuid(), t.uuid());
+        if key != uuid {
+            return Err(LatticeError::KeyMismatch { key, uuid });
+        }
+        self.base_data = t;
+        Ok(())
+    }
 
-    fn get_pending(&mut self) -> &mut HashMap<String, T>;
-    fn get_fulfilled(&mut self) -> &mut HashMap<String, T>;
+    fn set_provenance(&mut self, p: Option<Provenance>) {
+        self.provenance = p;
+    }
 
-    fn append_pending(&mut self, t: T) {
-        self.get_pending().insert(t.uuid(), t);
+    fn set_origin(&mut self, o: Option<Origin>) {
+        self.origin = o;
     }
 
-    fn append_fulfilled(&mut self, t: T) {
-        self.get_fulfilled().insert(t.uuid(), t);
+    fn set_data(&mut self, t: T) -> T {
+        std::mem::replace(&mut self.base_data, t)
     }
 
-    fn append(&mut self, t: T) {
-        if t.is_pending() {
-            self.append_pending(t);
-        } else {
-            self.append_fulfilled(t);
-        };
+    fn set_skipped(&mut self, s: Option<SkipPolicy>) {
+        self.skipped = s;
     }
+}
 
-    // Always public below here:
-    fn fulfill(&mut self, key: String) -> Result<(), ()> {
-        let mut cascasde = Vec::<String>::new();
-        let pending = self.get_pending();
-        match pending.remove(&key) {
-            None => Err(()),
-            Some(mut target) => {
-                let r_map = target.get_required_by();
-                for (k, _) in r_map {
-                    match pending.get_mut(k) {
-                        None => return Err(()),
-                        Some(x) => match x.depend_fulfilled(key.clone()) {
-                            Ok(()) => {
-                                if !x.is_pending() {
-                                    cascasde.push(x.uuid());
-                                }
-                            }
-                            Err(()) => return Err(()),
-                        },
-                    };
-                }
+// Fulfillment records a single LatNode leaving pending, see
+// LatMachine::fulfillment_order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fulfillment {
+    pub key: String,
+    // the LatNode whose fulfillment cascaded into this one, None when it
+    // was fulfilled directly.
+    pub triggered_by: Option<String>,
+    pub origin: Origin,
+}
 
-                self.get_fulfilled().insert(key, target);
+// NodeState is where a LatNode stands in the lattice.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NodeState {
+    // pending with nothing left to wait on.
+    Ready,
+    // pending on at least one unfulfilled dependency.
+    Blocked,
+    // pending for good, skipped with SkipPolicy::Blocks.
+    Skipped,
+    // parked by LatMachine::fail.
+    Failed,
+    // pending on a failed LatNode, directly or further upstream.
+    BlockedByFailure,
+    Fulfilled,
+    // referenced but never appended, see Placeholder.
+    Placeholder,
+}
 
-                for v in cascasde {
-                    match self.fulfill(v) {
-                        Err(()) => return Err(()),
-                        Ok(()) => {}
-                    };
-                }
+// StateFilter selects LatNodes by state for LatMachine::export_state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateFilter {
+    All,
+    // Ready or Blocked.
+    Pending,
+    Only(NodeState),
+}
 
-                Ok(())
-            }
+impl StateFilter {
+    pub fn matches(&self, state: NodeState) -> bool {
+        match self {
+            StateFilter::All => true,
+            StateFilter::Pending => state == NodeState::Ready || state == NodeState::Blocked,
+            StateFilter::Only(s) => *s == state,
         }
     }
+}
 
-    // TODO: IMPLEMENT
-    fn unfulfill(&mut self, key: String) -> Result<(), ()> {
-        Ok(())
-        // let mut cascasde = Vec::<String>::new();
-        // let pending = self.get_pending();
-        // match pending.remove(&key) {
-        //     None => Err(()),
-        //     Some(mut target) => {
-        //         let r_map = target.get_required_by();
-        //         for (k, _) in r_map {
-        //             match pending.get_mut(k) {
-        //                 None => return Err(()),
-        //                 Some(x) => match x.depend_fulfilled(key.clone()) {
-        //                     Ok(()) => {
-        //                         if !x.is_pending() {
-        //                             cascasde.push(x.uuid());
-        //                         }
-        //                     }
-        //                     Err(()) => return Err(()),
-        //                 },
-        //             };
-        //         }
-        //         self.get_fulfilled().insert(key, target);
-        //         for v in cascasde {
-        //             match self.fulfill(v) {
-        //                 Err(()) => return Err(()),
-        //                 Ok(()) => {}
-        //             };
-        //         }
-        //         Ok(())
-        //     }
-        // }
-
-    }
-
-    fn update_value(&mut self, key: String, update: U) -> Result<(), ()> {
-        let m = self.get_pending();
+// RemoveMode decides what LatMachine::remove does with the LatNodes that
+// require the one removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemoveMode {
+    // keep them, dropping their edges to it.
+    Orphan,
+    // remove them too, and everything requiring them in turn.
+    Cascade,
+}
 
-        // TODO: REPORT ERRS, DYING HERE WOULD BE AWFUL.
-        let x = m.get_mut(&key);
-        match x {
-            None => Err(()),
-            Some(t) => match t.update(update) {
-                Err(()) => Err(()),
-                Ok(()) => {
-                    if t.is_completed() {
-                        match self.fulfill(key) {
-                            Err(()) => return Err(()),
-                            Ok(()) => return Ok(()),
-                        };
-                    };
-                    Ok(())
-                }
-            },
-        }
-    }
+// Direction picks which way LatMachine::subgraph walks from its root.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    // everything the root depends on, directly or through others.
+    Dependencies,
+    // everything depending on the root, directly or through others.
+    Dependents,
+}
 
-    // boolean indicates whether this relationship blocks the value at is_required_by
-    fn update_required_by(&mut self, target: String, is_required_by: String) -> Result<bool, ()> {
-        // Is the required elm in pending?
-        match self.get_pending().get_mut(&target) {
-            Some(t) => {
-                t.add_required_by(is_required_by);
-                // It IS blocking
-                Ok(true)
-            }
+// StateRecord is a payload free view of one LatNode, see
+// LatMachine::export_state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateRecord {
+    pub key: String,
+    pub state: NodeState,
+    // the LatNode's position in fulfillment_order, counting from 1, None
+    // if the lattice never fulfilled it.
+    pub fulfilled_seq: Option<usize>,
+    // empty for placeholders.
+    pub artifacts: Vec<Artifact>,
+    // None unless fulfilled.
+    pub origin: Option<Origin>,
+}
 
-            // The required elm must be fulfilled:
-            None => match self.get_fulfilled().get_mut(&target) {
-                // if not err.
-                None => Err(()),
-                Some(t) => {
-                    t.add_required_by(is_required_by);
-                    // It is NOT blocking.
-                    Ok(false)
-                }
-            },
-        }
+// FulfillPolicy decides whether update_value fulfills a LatNode as soon
+// as its data reports completed. NodeType::auto_fulfill can override it
+// per LatNode.
+#[derive(Default)]
+pub enum FulfillPolicy<T> {
+    // fulfill straight away, the default.
+    #[default]
+    Auto,
+    // leave the LatNode pending until fulfill is called for it.
+    Manual,
+    // fulfill only if the callback signs off on the updated LatNode.
+    Confirm(Box<dyn Fn(&T) -> bool>),
+}
+
+// CancelToken lets another thread (or a callback) stop a long running
+// operation. Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    fn update_depends_on(&mut self, target: String, depends_on: String) -> Result<(), ()> {
-        // Is the required elm in pending?
-        match self.get_pending().get_mut(&target) {
-            Some(t) => {
-                t.add_depends_on(depends_on);
-                Ok(())
-            }
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
 
-            // The required elm must be fulfilled:
-            None => match self.get_fulfilled().remove(&target) {
-                // Unknown key in either map.
-                None => Err(()),
-                Some(mut t) => {
-                    t.add_depends_on(depends_on);
-                    self.append(t);
-                    Ok(())
-                }
-            },
-        }
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
     }
+}
 
-    fn add_requirement(&mut self, requires: String, is_required: String) -> Result<(), ()> {
-        // search for requires
-        let mut is_still_required = false;
+// DuplicatePolicy decides what append does with a LatNode whose key the
+// lattice already holds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    // fail with KeyTaken, the default.
+    #[default]
+    Reject,
+    // keep the existing LatNode, dropping the new one.
+    Keep,
+    // keep the existing LatNode, adding the new one's edges and references
+    // to it.
+    MergeEdges,
+    // swap the existing LatNode for the new one, see replace_node.
+    Replace,
+}
 
-        match self.get_pending().get_mut(&is_required) {
-            Some(is_req) => {
-                is_still_required = true;
-                is_req.add_required_by(requires.clone());
-            }
-            None => match self.get_fulfilled().get_mut(&is_required) {
-                None => return Err(()),
-                Some(is_req) => {
-                    is_req.add_required_by(requires.clone());
-                }
-            },
-        };
+// Limits guards a lattice against operations that touch too much of it
+// at once, None leaves a limit off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    // most LatNodes a single fulfill may move to fulfilled, itself included.
+    pub max_cascade_size: Option<usize>,
+    // longest chain of cascades a single fulfill may set off, counting the
+    // fulfilled LatNode itself as depth 1.
+    pub max_cascade_depth: Option<usize>,
+    // most nodes plus edges a single Changeset commit may add.
+    pub max_mutation: Option<usize>,
+    // no LatNodes or edges may be added or removed, see LatMachine::freeze.
+    pub frozen: bool,
+    // reject any edge to a key the lattice doesn't hold when it is made,
+    // rather than leaving it for LatMachine::unresolved to report. On by
+    // default with the `strict` feature.
+    pub strict_keys: bool,
+    // what append does with a key the lattice already holds.
+    pub on_duplicate: DuplicatePolicy,
+}
 
-        match self.get_pending().get_mut(&requires) {
-            Some(req) => {
-                req.add_depends_on(is_required.clone());
-                if !is_still_required {
-                    req.depend_fulfilled(is_required).unwrap();
-                }
-            }
+// Not derived, strict_keys follows the `strict` feature.
+#[allow(clippy::derivable_impls)]
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_cascade_size: None,
+            max_cascade_depth: None,
+            max_mutation: None,
+            frozen: false,
+            strict_keys: cfg!(feature = "strict"),
+            on_duplicate: DuplicatePolicy::Reject,
+        }
+    }
+}
 
-            None => {
-                if is_still_required {
-                    match self.get_fulfilled().remove(&requires) {
-                        None => return Err(()),
-                        Some(mut req) => {
-                            req.add_depends_on(is_required);
-                            self.append(req);
-                        }
-                    }
-                } else {
-                    match self.get_fulfilled().get_mut(&requires) {
-                        None => return Err(()),
-                        Some(req) => {
-                            req.add_depends_on(is_required.clone());
-                            req.depend_fulfilled(is_required).unwrap();
-                        }
-                    }
-                }
-            }
-        };
+// SyncCorrections is what a client must do to its copy of a lattice to
+// match the server after LatMachine::sync_from, each list sorted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyncCorrections {
+    // fulfilled on the server, still pending on the client.
+    pub fulfill: Vec<String>,
+    // fulfilled on the client but rejected by the server, the client must
+    // move these back to pending.
+    pub revert: Vec<String>,
+    // held by the server but missing from the client.
+    pub missing: Vec<String>,
+    // held by the client but unknown to the server.
+    pub unknown: Vec<String>,
+}
 
-        Ok(())
+impl SyncCorrections {
+    pub fn is_empty(&self) -> bool {
+        self.fulfill.is_empty()
+            && self.revert.is_empty()
+            && self.missing.is_empty()
+            && self.unknown.is_empty()
     }
 }
 
-pub struct BasicLattice<T> {
-    pending: HashMap<String, T>,
-    fulfilled: HashMap<String, T>,
+// Inconsistency is one way a lattice's bookkeeping disagrees with itself,
+// see LatMachine::validate.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Inconsistency {
+    // node holds an edge to a key that is neither a LatNode nor a
+    // placeholder.
+    Dangling {
+        node: String,
+        reference: String,
+    },
+    // node depends on dep, but dep doesn't list node in required_by.
+    MissingRequiredBy {
+        node: String,
+        dep: String,
+    },
+    // node lists dependent in required_by, but dependent doesn't depend
+    // on node.
+    MissingDependsOn {
+        node: String,
+        dependent: String,
+    },
+    // the key is both pending and fulfilled.
+    PendingAndFulfilled(String),
+    // a fulfilled LatNode still waiting on these keys.
+    FulfilledWhileWaiting {
+        node: String,
+        depends_on: Vec<String>,
+    },
 }
 
-impl<T: NodeType> BasicLattice<BasicNode<T>>
-where
-    T: NodeType,
-{
-    pub fn from_node_list(v: Vec<BasicNode<T>>) -> BasicLattice<BasicNode<T>> {
-        let mut s = BasicLattice::new();
-        let mut keys = Vec::<String>::new();
+// ValidationReport collects every Inconsistency found, sorted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub problems: Vec<Inconsistency>,
+}
 
-        for node in v {
-            if !node.is_pending() {
-                keys.push(node.uuid().to_string());
-            }
+impl ValidationReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
 
-            s.append_pending(node);
-        }
+// Health summarises whether a lattice can make progress, see
+// LatMachine::health. Every list is sorted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Health {
+    pub pending: usize,
+    pub fulfilled: usize,
+    pub placeholders: usize,
+    pub frozen: bool,
+    // see LatMachine::detect_cycles.
+    pub cycles: Vec<Vec<String>>,
+    // see LatMachine::unresolved.
+    pub unresolved: Vec<(String, String)>,
+    // pending LatNodes that can never unblock: they wait, directly or
+    // through other pending LatNodes, on a cycle or a key the lattice
+    // doesn't hold.
+    pub stuck: Vec<String>,
+    // ready LatNodes whose fulfill the lattice's Limits would refuse.
+    pub over_limits: Vec<String>,
+    // see LatMachine::validate.
+    pub inconsistencies: Vec<Inconsistency>,
+}
 
-        for key in keys {
-            s.fulfill(key);
-        }
+impl Health {
+    // is_healthy reports whether every pending LatNode can still be
+    // fulfilled and every edge resolves. Being frozen doesn't count
+    // against it.
+    pub fn is_healthy(&self) -> bool {
+        self.cycles.is_empty()
+            && self.unresolved.is_empty()
+            && self.stuck.is_empty()
+            && self.over_limits.is_empty()
+            && self.inconsistencies.is_empty()
+    }
+}
 
-        s
+// Placeholder stands in for a key that edges were made to, in lenient
+// mode, before a LatNode with that key was appended. It holds the other
+// ends of those edges and is merged into the LatNode once appended.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Placeholder {
+    // keys it was made to depend on that aren't fulfilled yet.
+    pub depends_on: HashMap<String, ()>,
+    // keys made to depend on it.
+    pub required_by: HashMap<String, ()>,
+    // keys it was made to depend on that have since been fulfilled.
+    pub fulfilled_by: HashMap<String, ()>,
+}
+
+impl Placeholder {
+    fn is_empty(&self) -> bool {
+        self.depends_on.is_empty() && self.required_by.is_empty() && self.fulfilled_by.is_empty()
+    }
+}
+
+// merge_placeholder folds any placeholder for t's key into t, returning the
+// keys that were waiting on it.
+fn merge_placeholder<L, T, U>(l: &mut L, t: &mut T) -> Vec<String>
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    let p = match l.get_placeholders().remove(&t.uuid()) {
+        None => return Vec::new(),
+        Some(p) => p,
+    };
+
+    for d in p.depends_on.into_keys() {
+        if l.read_fulfilled().contains_key(&d) {
+            t.get_fulfilled_by().insert(d, ());
+        } else {
+            t.add_depends_on(d);
+        }
+    }
+    for d in p.fulfilled_by.into_keys() {
+        t.get_fulfilled_by().insert(d, ());
+    }
+
+    let waiting: Vec<String> = p.required_by.into_keys().collect();
+    for r in &waiting {
+        t.add_required_by(r.clone());
+    }
+    waiting
+}
+
+// make_placeholders gives every key t has an edge to, that the lattice
+// doesn't hold, a placeholder holding the other end.
+fn make_placeholders<L, T, U>(l: &mut L, t: &T)
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    let key = t.uuid();
+    for d in t.depends_on().keys().chain(t.fulfilled_by().keys()) {
+        if l.read_node(d).is_none() {
+            let p = l.get_placeholders().entry(d.clone()).or_default();
+            p.required_by.insert(key.clone(), ());
+        }
+    }
+
+    for r in t.required_by().keys() {
+        if l.read_node(r).is_none() {
+            let p = l.get_placeholders().entry(r.clone()).or_default();
+            if t.is_pending() {
+                p.depends_on.insert(key.clone(), ());
+            } else {
+                p.fulfilled_by.insert(key.clone(), ());
+            }
+        }
+    }
+}
+
+// cache_key is what t's work is stored under in a FulfillmentCache: its
+// fingerprint, then every dependency's artifact hashes, one
+// "producer/name=hash" line each, sorted. None without a fingerprint, or if
+// any dependency is missing or has an artifact without a hash.
+fn cache_key<L, T, U>(l: &L, t: &T) -> Option<String>
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    let mut key = t.fingerprint()?;
+    let mut deps: Vec<&String> = t
+        .depends_on()
+        .keys()
+        .chain(t.fulfilled_by().keys())
+        .collect();
+    deps.sort();
+    for d in deps {
+        let mut artifacts: Vec<&Artifact> = l.read_node(d)?.artifacts().iter().collect();
+        artifacts.sort_by(|a, b| a.name.cmp(&b.name));
+        for a in artifacts {
+            key.push_str(&format!("\n{}/{}={}", d, a.name, a.hash.as_ref()?));
+        }
+    }
+    Some(key)
+}
+
+// cached_entry returns what the lattice's FulfillmentCache holds for key's
+// work, if key is a ready LatNode that may be fulfilled.
+fn cached_entry<L, T, U>(l: &L, key: &str) -> Option<CacheEntry>
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    let cache = l.read_cache().as_ref()?;
+    let t = l.read_pending().get(key)?;
+    if t.is_blocked() || t.skipped().is_some() || l.read_failed().contains_key(key) {
+        return None;
+    }
+    // a cache that can't be reached misses.
+    cache.get(&cache_key(l, t)?).ok().flatten()
+}
+
+// cache_hit fulfills key from the lattice's FulfillmentCache if it is
+// ready and its work is found there, cascading as fulfill does, and
+// reports whether it was. A cache hit that would break the Limits is left
+// alone.
+fn cache_hit<L, T, U>(l: &mut L, key: &str) -> bool
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    let entry = match cached_entry(l, key) {
+        None => return false,
+        Some(e) => e,
+    };
+    if l.check_limits(key).is_err() {
+        return false;
+    }
+
+    cache_log!(node = key, decision = "hit"; "{} found in the build cache", key);
+    if let Some(t) = l.get_pending().get_mut(key) {
+        take_entry(t, entry);
+    }
+    if l.fulfill_from(key.to_string(), None, &mut || true).is_err() {
+        if let Some(t) = l.get_pending().get_mut(key) {
+            t.set_origin(None);
+        }
+        return false;
+    }
+    true
+}
+
+// store_entries puts the entries a cascade fulfilled into the lattice's
+// FulfillmentCache. The work is done whether or not they are stored.
+fn store_entries<L, T, U>(l: &mut L, stores: Vec<(String, CacheEntry)>)
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    if let Some(c) = l.get_cache() {
+        for (ck, entry) in stores {
+            let _ = c.put(&ck, &entry);
+        }
+    }
+}
+
+// take_entry gives t a cache hit's artifacts and its Origin, which
+// fulfill_from goes by to tell a hit from work done here.
+fn take_entry<T, U>(t: &mut T, entry: CacheEntry)
+where
+    T: WriteNode<U>,
+{
+    *t.get_artifacts() = entry.artifacts;
+    t.set_origin(Some(Origin {
+        run: entry.run,
+        kind: OriginKind::Cache,
+    }));
+}
+
+// put_artifact adds a to v, replacing any artifact of the same name.
+fn put_artifact(v: &mut Vec<Artifact>, a: Artifact) {
+    match v.iter_mut().find(|x| x.name == a.name) {
+        Some(x) => *x = a,
+        None => v.push(a),
+    }
+}
+
+// admit runs every check append_approved makes of t without touching the
+// lattice or t. replacing says t takes the place of the LatNode already
+// under its key, rather than being appended.
+fn admit<L, T, U>(
+    l: &mut L,
+    t: &T,
+    replacing: bool,
+    token: Option<&ApprovalToken>,
+) -> Result<(), LatticeError>
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    let key = t.uuid();
+    let change = match replacing {
+        true => StructuralChange::Replace(&key),
+        false => StructuralChange::Append(&key),
+    };
+    l.approve(&change, token)?;
+    if l.read_aliases().contains_key(&key) {
+        return Err(LatticeError::KeyTaken(key));
+    }
+    // read_node sees through aliases, so t's relations needn't be
+    // canonical yet.
+    l.check_known(
+        t.depends_on()
+            .keys()
+            .chain(t.required_by().keys())
+            .chain(t.references().keys()),
+    )
+}
+
+// replace is replace_node once t is admitted.
+fn replace<L, T, U>(l: &mut L, t: T) -> NodeHandle
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    let key = t.uuid();
+    let (was_fulfilled, old) = match l.get_fulfilled().remove(&key) {
+        Some(old) => (true, old),
+        None => match l.get_pending().remove(&key) {
+            Some(old) => (false, old),
+            None => return place(l, t),
+        },
+    };
+
+    // dependents still list key, keep the edges back to them.
+    let mut t = t;
+    for r in old.required_by().keys() {
+        t.add_required_by(r.clone());
+    }
+    canonicalize_relations(l.read_aliases(), &mut t);
+    if !l.read_limits().strict_keys {
+        make_placeholders(l, &t);
+    }
+    if was_fulfilled && !t.is_pending() {
+        return l.append_fulfilled(t);
+    }
+
+    let done = !t.is_pending();
+    let h = l.append_pending(t);
+    if done {
+        let _ = l.fulfill_from(key, None, &mut || true);
+    }
+    h
+}
+
+// place adds t, already admitted, to pending or fulfilled.
+fn place<L, T, U>(l: &mut L, t: T) -> NodeHandle
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    let mut t = t;
+    canonicalize_relations(l.read_aliases(), &mut t);
+    let waiting = merge_placeholder(l, &mut t);
+    if !l.read_limits().strict_keys {
+        make_placeholders(l, &t);
+    }
+
+    if t.is_pending() {
+        l.append_pending(t)
+    } else {
+        // whatever waited on the placeholder is waiting no longer.
+        let key = t.uuid();
+        for k in waiting {
+            if let Some(x) = l.get_pending().get_mut(&k) {
+                let _ = x.depend_fulfilled(key.clone());
+            }
+        }
+        l.append_fulfilled(t)
+    }
+}
+
+// placeholder_requirement is add_requirement where requires, is_required
+// or both are only known as placeholders.
+fn placeholder_requirement<L, T, U>(l: &mut L, requires: String, is_required: String)
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    let still_required = !l.read_fulfilled().contains_key(&is_required);
+
+    match l.get_pending().get_mut(&is_required) {
+        Some(x) => x.add_required_by(requires.clone()),
+        None => match l.get_fulfilled().get_mut(&is_required) {
+            Some(x) => x.add_required_by(requires.clone()),
+            None => {
+                let p = l.get_placeholders().entry(is_required.clone()).or_default();
+                p.required_by.insert(requires.clone(), ());
+            }
+        },
+    }
+
+    if let Some(x) = l.get_pending().get_mut(&requires) {
+        x.add_depends_on(is_required.clone());
+        if !still_required {
+            let _ = x.depend_fulfilled(is_required);
+        }
+    } else if let Some(mut x) = l.get_fulfilled().remove(&requires) {
+        x.add_depends_on(is_required.clone());
+        if still_required && x.is_blocked() {
+            l.append_pending(x);
+        } else if still_required {
+            l.get_fulfilled().insert(requires, x);
+        } else {
+            let _ = x.depend_fulfilled(is_required);
+            l.get_fulfilled().insert(requires, x);
+        }
+    } else {
+        let p = l.get_placeholders().entry(requires).or_default();
+        if still_required {
+            p.depends_on.insert(is_required, ());
+        } else {
+            p.fulfilled_by.insert(is_required, ());
+        }
+    }
+}
+
+// self_edge fails with SelfDependency if t depends on or is required by
+// itself.
+fn self_edge<T, U>(t: &T) -> Result<(), LatticeError>
+where
+    T: ReadNode<U>,
+{
+    let key = t.uuid();
+    match t.depends_on().contains_key(&key) || t.required_by().contains_key(&key) {
+        true => Err(LatticeError::SelfDependency(key)),
+        false => Ok(()),
+    }
+}
+
+// check_node_list fails on the first key of v held by an earlier node too,
+// then on the first node with a self edge.
+fn check_node_list<T: NodeType>(v: &[BasicNode<T>]) -> Result<(), LatticeError> {
+    let mut seen = HashMap::<String, ()>::with_capacity(v.len());
+    if let Some(key) = v
+        .iter()
+        .map(|node| node.uuid())
+        .find(|key| seen.insert(key.clone(), ()).is_some())
+    {
+        return Err(LatticeError::KeyTaken(key));
+    }
+    v.iter().try_for_each(self_edge)
+}
+
+// backfill completes every edge between nodes of v that only one end
+// declares: a depends_on gets the matching required_by, and the other way
+// round. Edges to keys outside v are left as they are.
+fn backfill<T: NodeType>(v: &mut [BasicNode<T>]) {
+    let index: HashMap<String, usize> = v.iter().enumerate().map(|(i, n)| (n.uuid(), i)).collect();
+
+    // (node, key, whether key goes in depends_on)
+    let mut missing = Vec::<(usize, String, bool)>::new();
+    for n in v.iter() {
+        let key = n.uuid();
+        for d in n.depends_on().keys() {
+            if let Some(&j) = index.get(d) {
+                if !v[j].required_by().contains_key(&key) {
+                    missing.push((j, key.clone(), false));
+                }
+            }
+        }
+        for r in n.required_by().keys() {
+            if let Some(&j) = index.get(r) {
+                if !v[j].depends_on().contains_key(&key) {
+                    missing.push((j, key.clone(), true));
+                }
+            }
+        }
+    }
+
+    for (j, key, depends_on) in missing {
+        match depends_on {
+            true => v[j].add_depends_on(key),
+            false => v[j].add_required_by(key),
+        }
+    }
+}
+
+// forget finishes removing LatNodes already taken out of pending and
+// fulfilled: their handles are retired, their aliases dropped and every
+// edge, reference and placeholder relation to them scrubbed.
+fn forget<L, T, U>(l: &mut L, removed: &HashMap<String, ()>)
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    for k in removed.keys() {
+        l.get_handles().release(k);
+    }
+    l.get_aliases().retain(|_, k| !removed.contains_key(k));
+    l.get_forced().retain(|k, _| !removed.contains_key(k));
+    l.get_failed().retain(|k, _| !removed.contains_key(k));
+    let grouped: Vec<String> = l
+        .read_pending()
+        .iter()
+        .chain(l.read_fulfilled().iter())
+        .filter(|(_, t)| !t.quorums().is_empty())
+        .map(|(k, _)| k.clone())
+        .collect();
+    for k in grouped {
+        spend_quorums(l, &k, removed);
+    }
+    scrub_edges(l.get_pending(), removed);
+    scrub_edges(l.get_fulfilled(), removed);
+    scrub_placeholders(l.get_placeholders(), removed);
+}
+
+// rekey_in moves old's entry in m, if any, to new.
+fn rekey_in(m: &mut HashMap<String, ()>, old: &str, new: &str) {
+    if m.remove(old).is_some() {
+        m.insert(new.to_string(), ());
+    }
+}
+
+// rekey_edges moves every relation the nodes in m hold to old over to new.
+fn rekey_edges<T, U>(m: &mut HashMap<String, T>, old: &str, new: &str)
+where
+    T: WriteNode<U>,
+{
+    for x in m.values_mut() {
+        rekey_in(x.get_depends_on(), old, new);
+        rekey_in(x.get_required_by(), old, new);
+        rekey_in(x.get_fulfilled_by(), old, new);
+        rekey_in(x.get_references(), old, new);
+        for m in x.get_quorums().iter_mut().flat_map(|q| &mut q.members) {
+            if m == old {
+                *m = new.to_string();
+            }
+        }
+    }
+}
+
+// scrub_edges drops every relation the nodes in m hold to removed keys.
+fn scrub_edges<T, U>(m: &mut HashMap<String, T>, removed: &HashMap<String, ()>)
+where
+    T: WriteNode<U>,
+{
+    if removed.is_empty() {
+        return;
+    }
+
+    let keep = |k: &String, _: &mut ()| !removed.contains_key(k);
+    for t in m.values_mut() {
+        t.get_depends_on().retain(keep);
+        t.get_required_by().retain(keep);
+        t.get_fulfilled_by().retain(keep);
+        t.get_references().retain(keep);
+        prune_quorums(t);
+    }
+}
+
+// drop_edge takes requires' edge to is_required out at both ends,
+// placeholders included, without checking anything or moving either.
+fn drop_edge<L, T, U>(l: &mut L, requires: &str, is_required: &str)
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    let t = match l.get_pending().get_mut(requires) {
+        Some(t) => Some(t),
+        None => l.get_fulfilled().get_mut(requires),
+    };
+    match t {
+        Some(t) => {
+            t.get_depends_on().remove(is_required);
+            t.get_fulfilled_by().remove(is_required);
+            prune_quorums(t);
+        }
+        None => {
+            if let Some(p) = l.get_placeholders().get_mut(requires) {
+                p.depends_on.remove(is_required);
+                p.fulfilled_by.remove(is_required);
+            }
+        }
+    }
+
+    let t = match l.get_pending().get_mut(is_required) {
+        Some(t) => Some(t),
+        None => l.get_fulfilled().get_mut(is_required),
+    };
+    match t {
+        Some(t) => {
+            t.get_required_by().remove(requires);
+        }
+        None => {
+            if let Some(p) = l.get_placeholders().get_mut(is_required) {
+                p.required_by.remove(requires);
+            }
+        }
+    }
+    l.get_placeholders().retain(|_, p| !p.is_empty());
+}
+
+// prune_quorums drops the members of t's quorums t holds no edge to, and
+// the quorums left empty.
+fn prune_quorums<T, U>(t: &mut T)
+where
+    T: WriteNode<U>,
+{
+    if t.quorums().is_empty() {
+        return;
+    }
+
+    let edges: HashMap<String, ()> = t
+        .depends_on()
+        .keys()
+        .chain(t.fulfilled_by().keys())
+        .map(|k| (k.clone(), ()))
+        .collect();
+    let quorums = t.get_quorums();
+    for q in quorums.iter_mut() {
+        q.members.retain(|m| edges.contains_key(m));
+    }
+    quorums.retain(|q| !q.members.is_empty());
+}
+
+// spend_quorums is for requires losing its edges to gone: a Quorum that is
+// met, but won't be without the fulfilled members in gone, is spent, and
+// the edges to its unfulfilled members are dropped as well, at both ends.
+// Losing a fulfilled dependency never sets a LatNode waiting again, as
+// with plain ones.
+fn spend_quorums<L, T, U>(l: &mut L, requires: &str, gone: &HashMap<String, ()>)
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    let t = match l.read_node(requires) {
+        None => return,
+        Some(t) => t,
+    };
+    let done = |m: &&String| t.fulfilled_by().contains_key(*m);
+    let mut spent = Vec::<String>::new();
+    for q in t.quorums() {
+        let kept = q
+            .members
+            .iter()
+            .filter(|m| done(m) && !gone.contains_key(*m))
+            .count();
+        if q.is_met(t.fulfilled_by()) && kept < q.need {
+            spent.extend(
+                q.members
+                    .iter()
+                    .filter(|m| !done(m) && !gone.contains_key(*m))
+                    .cloned(),
+            );
+        }
+    }
+    if spent.is_empty() {
+        return;
+    }
+
+    let t = match l.get_pending().get_mut(requires) {
+        Some(t) => t,
+        None => l.get_fulfilled().get_mut(requires).unwrap(),
+    };
+    for m in &spent {
+        t.get_depends_on().remove(m);
+        t.get_fulfilled_by().remove(m);
+    }
+    prune_quorums(t);
+    for m in &spent {
+        if let Some(x) = l.get_pending().get_mut(m) {
+            x.get_required_by().remove(requires);
+        } else if let Some(x) = l.get_fulfilled().get_mut(m) {
+            x.get_required_by().remove(requires);
+        } else if let Some(p) = l.get_placeholders().get_mut(m) {
+            p.required_by.remove(requires);
+        }
+    }
+    l.get_placeholders().retain(|_, p| !p.is_empty());
+}
+
+// not_pending is the error for key when it isn't pending: AlreadyFulfilled
+// if the lattice has fulfilled it, UnknownKey otherwise.
+fn not_pending<L, T, U>(l: &L, key: String) -> LatticeError
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    match l.read_fulfilled().contains_key(&key) {
+        true => LatticeError::AlreadyFulfilled(key),
+        false => LatticeError::UnknownKey(key),
+    }
+}
+
+// unblocked fails with Blocked if key is pending and still waits on a
+// dependency, which only force_fulfill overrides.
+fn unblocked<L, T, U>(l: &L, key: &str) -> Result<(), LatticeError>
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    match l.read_pending().get(key) {
+        Some(t) if t.is_blocked() => Err(LatticeError::Blocked(key.to_string())),
+        _ => Ok(()),
+    }
+}
+
+// closing_cycle returns the cycle that making requires depend on
+// is_required would close, as keys each depending on the next and the last
+// depending on the first: requires, is_required, then the shortest chain of
+// dependencies leading back to requires. None if the edge is safe.
+fn closing_cycle<'a, L, T, U>(
+    l: &'a L,
+    requires: &'a str,
+    is_required: &'a str,
+) -> Option<Vec<String>>
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U> + 'a,
+{
+    if requires == is_required {
+        return Some(vec![requires.to_string()]);
+    }
+
+    // key -> the key whose dependency it was found through.
+    let mut parent = HashMap::<&str, &str>::new();
+    parent.insert(is_required, is_required);
+    let mut queue = VecDeque::from(vec![is_required]);
+    while let Some(k) = queue.pop_front() {
+        let t = match l.read_node(k) {
+            None => continue,
+            Some(t) => t,
+        };
+        for d in t.depends_on().keys().chain(t.fulfilled_by().keys()) {
+            if d == requires {
+                let mut cycle = vec![k.to_string()];
+                let mut at = k;
+                while at != is_required {
+                    at = parent[at];
+                    cycle.push(at.to_string());
+                }
+                cycle.push(requires.to_string());
+                cycle.reverse();
+                return Some(cycle);
+            }
+            if let hash_map::Entry::Vacant(e) = parent.entry(d) {
+                e.insert(k);
+                queue.push_back(d);
+            }
+        }
+    }
+
+    None
+}
+
+// resolved is LatMachine::canonical for an owned key.
+fn resolved<L, T, U>(l: &L, key: String) -> String
+where
+    L: LatMachine<T, U> + ?Sized,
+    T: WriteNode<U>,
+{
+    match l.read_aliases().get(&key) {
+        Some(c) => c.clone(),
+        None => key,
+    }
+}
+
+// canonicalize_relations replaces every alias in t's relation maps with
+// the key it stands for.
+fn canonicalize_relations<T, U>(aliases: &HashMap<String, String>, t: &mut T)
+where
+    T: WriteNode<U>,
+{
+    if aliases.is_empty() {
+        return;
+    }
+
+    let fix = |m: &mut HashMap<String, ()>| {
+        let found: Vec<String> = m
+            .keys()
+            .filter(|k| aliases.contains_key(*k))
+            .cloned()
+            .collect();
+        for a in found {
+            m.remove(&a);
+            m.insert(aliases[&a].clone(), ());
+        }
+    };
+    fix(t.get_depends_on());
+    fix(t.get_required_by());
+    fix(t.get_fulfilled_by());
+    fix(t.get_references());
+    for m in t.get_quorums().iter_mut().flat_map(|q| &mut q.members) {
+        if let Some(c) = aliases.get(m) {
+            *m = c.clone();
+        }
+    }
+}
+
+// scrub_placeholders drops every relation placeholders hold to removed
+// keys, and any placeholder left with none.
+fn scrub_placeholders(m: &mut HashMap<String, Placeholder>, removed: &HashMap<String, ()>) {
+    if removed.is_empty() {
+        return;
+    }
+
+    for p in m.values_mut() {
+        p.depends_on.retain(|k, _| !removed.contains_key(k));
+        p.required_by.retain(|k, _| !removed.contains_key(k));
+        p.fulfilled_by.retain(|k, _| !removed.contains_key(k));
+    }
+    m.retain(|_, p| !p.is_empty());
+}
+
+pub trait LatMachine<T, U>
+where
+    T: WriteNode<U>,
+{
+    // default impl?
+    fn new() -> Self;
+
+    // If embedding as a LatNode, then we get this for free.
+    fn is_completed(&self) -> bool {
+        self.read_pending().is_empty()
+    }
+
+    fn read_pending(&self) -> &HashMap<String, T>;
+    fn read_fulfilled(&self) -> &HashMap<String, T>;
+
+    fn get_pending(&mut self) -> &mut HashMap<String, T>;
+    fn get_fulfilled(&mut self) -> &mut HashMap<String, T>;
+
+    // read_node finds key in either the pending or fulfilled map.
+    fn read_node(&self, key: &str) -> Option<&T> {
+        match self.read_pending().get(key) {
+            Some(t) => Some(t),
+            None => match self.read_fulfilled().get(key) {
+                Some(t) => Some(t),
+                None => {
+                    let key = self.read_aliases().get(key)?;
+                    self.read_pending()
+                        .get(key)
+                        .or_else(|| self.read_fulfilled().get(key))
+                }
+            },
+        }
+    }
+
+    // alternate keys for LatNodes, alias -> canonical key.
+    fn read_aliases(&self) -> &HashMap<String, String>;
+    fn get_aliases(&mut self) -> &mut HashMap<String, String>;
+
+    // canonical returns the key alias was registered for, or key itself
+    // when it isn't an alias.
+    fn canonical<'a>(&'a self, key: &'a str) -> &'a str {
+        match self.read_aliases().get(key) {
+            Some(c) => c.as_str(),
+            None => key,
+        }
+    }
+
+    // add_alias lets alias stand in for key wherever a key is accepted:
+    // lookups, edges and appended LatNodes' relations. Edges are always
+    // stored against the canonical key. Err if key isn't in the lattice
+    // or alias is already a key, placeholder or alias for another key.
+    fn add_alias(&mut self, alias: String, key: String) -> Result<(), LatticeError> {
+        let key = resolved(self, key);
+        if self.read_pending().get(&key).is_none() && self.read_fulfilled().get(&key).is_none() {
+            return Err(LatticeError::UnknownKey(key));
+        }
+        if self.read_pending().contains_key(&alias)
+            || self.read_fulfilled().contains_key(&alias)
+            || self.read_placeholders().contains_key(&alias)
+        {
+            return Err(LatticeError::KeyTaken(alias));
+        }
+        self.permit(&Mutation::AddAlias {
+            alias: &alias,
+            key: &key,
+        })?;
+
+        match self.get_aliases().entry(alias) {
+            hash_map::Entry::Occupied(e) if *e.get() != key => {
+                Err(LatticeError::KeyTaken(e.key().clone()))
+            }
+            e => {
+                e.or_insert(key);
+                Ok(())
+            }
+        }
+    }
+
+    // remove_alias forgets alias, returning the key it stood for. None,
+    // leaving it registered, if the MutationPolicy refuses.
+    fn remove_alias(&mut self, alias: &str) -> Option<String> {
+        self.permit(&Mutation::RemoveAlias(alias)).ok()?;
+        self.get_aliases().remove(alias)
+    }
+
+    // aliases_of lists the aliases registered for key, sorted.
+    fn aliases_of(&self, key: &str) -> Vec<String> {
+        let mut v: Vec<String> = self
+            .read_aliases()
+            .iter()
+            .filter(|(_, c)| c.as_str() == key)
+            .map(|(a, _)| a.clone())
+            .collect();
+        v.sort();
+        v
+    }
+
+    fn read_fulfill_policy(&self) -> &FulfillPolicy<T>;
+    fn get_fulfill_policy(&mut self) -> &mut FulfillPolicy<T>;
+
+    fn read_limits(&self) -> &Limits;
+    fn get_limits(&mut self) -> &mut Limits;
+
+    // every fulfillment so far, in the order it happened.
+    fn read_fulfillment_log(&self) -> &Vec<Fulfillment>;
+    fn get_fulfillment_log(&mut self) -> &mut Vec<Fulfillment>;
+
+    // every key ever appended, interned as a NodeHandle.
+    fn read_handles(&self) -> &HandleTable;
+    fn get_handles(&mut self) -> &mut HandleTable;
+
+    // append_pending drops any Origin t carries, fulfillment records its
+    // own.
+    fn append_pending(&mut self, t: T) -> NodeHandle {
+        let mut t = t;
+        t.set_origin(None);
+        let key = t.uuid();
+        let h = self.get_handles().intern(&key);
+        self.get_pending().insert(key, t);
+        h
+    }
+
+    fn append_fulfilled(&mut self, t: T) -> NodeHandle {
+        let key = t.uuid();
+        let h = self.get_handles().intern(&key);
+        self.get_fulfilled().insert(key, t);
+        h
+    }
+
+    // append adds t to pending or fulfilled, returning a handle that can
+    // stand in for its key in later calls. Err if the lattice is frozen. A
+    // LatNode already under t's key is dealt with as Limits::on_duplicate
+    // says.
+    fn append(&mut self, t: T) -> Result<NodeHandle, LatticeError> {
+        self.append_approved(t, None)
+    }
+
+    // try_append is append that leaves an existing LatNode alone, failing
+    // with KeyTaken whatever the DuplicatePolicy. Filling in a placeholder
+    // is not a collision.
+    // On any failure t is handed back untouched alongside the error, ready
+    // to retry.
+    fn try_append(&mut self, t: T) -> Result<NodeHandle, (T, LatticeError)> {
+        self.try_append_approved(t, None)
+    }
+
+    // try_append_approved is try_append presenting token to the
+    // ChangeGate.
+    fn try_append_approved(
+        &mut self,
+        t: T,
+        token: Option<&ApprovalToken>,
+    ) -> Result<NodeHandle, (T, LatticeError)> {
+        let key = t.uuid();
+        if self.read_node(&key).is_some() {
+            return Err((t, LatticeError::KeyTaken(key)));
+        }
+        match admit(self, &t, false, token) {
+            Ok(()) => {
+                let h = place(self, t);
+                cache_hit(self, &key);
+                Ok(h)
+            }
+            Err(e) => Err((t, e)),
+        }
+    }
+
+    // append_approved is append presenting token to the ChangeGate.
+    fn append_approved(
+        &mut self,
+        t: T,
+        token: Option<&ApprovalToken>,
+    ) -> Result<NodeHandle, LatticeError> {
+        let key = t.uuid();
+        let held =
+            self.read_pending().contains_key(&key) || self.read_fulfilled().contains_key(&key);
+        let replacing = held
+            && matches!(
+                self.read_limits().on_duplicate,
+                DuplicatePolicy::Replace | DuplicatePolicy::MergeEdges
+            );
+        admit(self, &t, replacing, token)?;
+        if !held {
+            let h = place(self, t);
+            cache_hit(self, &key);
+            return Ok(h);
+        }
+
+        match self.read_limits().on_duplicate {
+            DuplicatePolicy::Reject => Err(LatticeError::KeyTaken(key)),
+            DuplicatePolicy::Keep => Ok(self.get_handles().intern(&key)),
+            DuplicatePolicy::Replace => Ok(replace(self, t)),
+            DuplicatePolicy::MergeEdges => {
+                // add_requirement handles the existing LatNode's state.
+                for d in t.depends_on().keys() {
+                    self.add_requirement_approved(key.clone(), d.clone(), token)?;
+                }
+                for r in t.required_by().keys() {
+                    self.add_requirement_approved(r.clone(), key.clone(), token)?;
+                }
+                for r in t.references().keys() {
+                    self.add_reference(key.clone(), r.clone())?;
+                }
+                Ok(self.get_handles().intern(&key))
+            }
+        }
+    }
+
+    // rename re-keys the LatNode under old to data's uuid, swapping data
+    // in, and rewrites every edge, reference, alias and placeholder
+    // relation to old, along with the fulfillment log. The LatNode keeps
+    // its state and relations. old's handle goes stale, the LatNode gets a
+    // new one. Err with KeyTaken if the new key is already in use, leaving
+    // everything as it was.
+    fn rename(&mut self, old: String, data: U) -> Result<NodeHandle, LatticeError> {
+        let old = resolved(self, old);
+        let (mut t, fulfilled) = match self.get_pending().remove(&old) {
+            Some(t) => (t, false),
+            None => match self.get_fulfilled().remove(&old) {
+                Some(t) => (t, true),
+                None => return Err(LatticeError::UnknownKey(old)),
+            },
+        };
+
+        let was = t.set_data(data);
+        let new = t.uuid();
+        let check = match new == old
+            || (self.read_node(&new).is_none() && !self.read_placeholders().contains_key(&new))
+        {
+            true => self.approve(
+                &StructuralChange::Rename {
+                    from: &old,
+                    to: &new,
+                },
+                None,
+            ),
+            false => Err(LatticeError::KeyTaken(new.clone())),
+        };
+        if let Err(e) = check {
+            t.set_data(was);
+            match fulfilled {
+                true => self.get_fulfilled().insert(old, t),
+                false => self.get_pending().insert(old, t),
+            };
+            return Err(e);
+        }
+
+        if new != old {
+            rekey_edges(self.get_pending(), &old, &new);
+            rekey_edges(self.get_fulfilled(), &old, &new);
+            for p in self.get_placeholders().values_mut() {
+                rekey_in(&mut p.depends_on, &old, &new);
+                rekey_in(&mut p.required_by, &old, &new);
+                rekey_in(&mut p.fulfilled_by, &old, &new);
+            }
+            for k in self.get_aliases().values_mut() {
+                if *k == old {
+                    *k = new.clone();
+                }
+            }
+            if let Some(skipped) = self.get_forced().remove(&old) {
+                self.get_forced().insert(new.clone(), skipped);
+            }
+            for skipped in self.get_forced().values_mut() {
+                for k in skipped.iter_mut().filter(|k| **k == old) {
+                    *k = new.clone();
+                }
+                skipped.sort();
+            }
+            if let Some(reason) = self.get_failed().remove(&old) {
+                self.get_failed().insert(new.clone(), reason);
+            }
+            for f in self.get_fulfillment_log().iter_mut() {
+                if f.key == old {
+                    f.key = new.clone();
+                }
+                if f.triggered_by.as_deref() == Some(old.as_str()) {
+                    f.triggered_by = Some(new.clone());
+                }
+            }
+            self.get_handles().release(&old);
+        }
+
+        Ok(match fulfilled {
+            true => self.append_fulfilled(t),
+            false => self.append_pending(t),
+        })
+    }
+
+    // replace_node swaps whatever LatNode holds t's key for t, whatever
+    // the DuplicatePolicy, or appends t if there is none. t brings its own
+    // dependencies and references and takes over the old LatNode's
+    // dependents. A completed t cascades as fulfill would. Dependents
+    // aren't otherwise touched, so to replace a fulfilled LatNode with a
+    // pending one and re-pend them, unfulfill it first.
+    fn replace_node(&mut self, t: T) -> Result<NodeHandle, LatticeError> {
+        let held = self.read_node(&t.uuid()).is_some();
+        admit(self, &t, held, None)?;
+        Ok(replace(self, t))
+    }
+
+    // freeze locks the lattice's shape: until thaw, appending, removing
+    // and changing edges or references fail unless the ChangeGate approves,
+    // while fulfill, update_value and other state transitions carry on as
+    // normal. freeze and thaw are put to the MutationPolicy.
+    fn freeze(&mut self) -> Result<(), LatticeError> {
+        self.permit(&Mutation::Freeze)?;
+        self.get_limits().frozen = true;
+        Ok(())
+    }
+
+    fn thaw(&mut self) -> Result<(), LatticeError> {
+        self.permit(&Mutation::Thaw)?;
+        self.get_limits().frozen = false;
+        Ok(())
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.read_limits().frozen
+    }
+
+    // check_known fails if the lattice has strict_keys set and any of keys
+    // is unknown to it.
+    fn check_known<'a, I>(&self, keys: I) -> Result<(), LatticeError>
+    where
+        I: IntoIterator<Item = &'a String>,
+    {
+        if !self.read_limits().strict_keys {
+            return Ok(());
+        }
+
+        match keys.into_iter().find(|k| self.read_node(k).is_none()) {
+            None => Ok(()),
+            Some(k) => Err(LatticeError::UnknownKey(k.clone())),
+        }
+    }
+
+    // detect_cycles returns every dependency cycle in the lattice, each as
+    // keys depending on the next with the last depending on the first.
+    // LatNodes in a cycle never unblock, so none of them can cascade.
+    // Breaking one edge of each cycle returned leaves the lattice acyclic.
+    fn detect_cycles(&self) -> Vec<Vec<String>> {
+        let mut keys: Vec<&String> = self
+            .read_pending()
+            .keys()
+            .chain(self.read_fulfilled().keys())
+            .collect();
+        keys.sort();
+        let index: HashMap<&String, usize> =
+            keys.iter().enumerate().map(|(i, k)| (*k, i)).collect();
+
+        let deps: Vec<Vec<usize>> = keys
+            .iter()
+            .map(|k| match self.read_node(k) {
+                None => Vec::new(),
+                Some(t) => {
+                    let mut d: Vec<usize> = t
+                        .depends_on()
+                        .keys()
+                        .chain(t.fulfilled_by().keys())
+                        .filter_map(|d| index.get(d).copied())
+                        .collect();
+                    d.sort_unstable();
+                    d
+                }
+            })
+            .collect();
+
+        find_cycles(0..keys.len(), &deps)
+            .into_iter()
+            .map(|(cycle, _)| cycle.into_iter().map(|i| keys[i].clone()).collect())
+            .collect()
+    }
+
+    // validate cross checks every LatNode's relations against the LatNodes
+    // they name, reporting dangling keys, edges only one end records, keys
+    // held as both pending and fulfilled, and fulfilled LatNodes still
+    // waiting on something. Half edges left by update_required_by or
+    // update_depends_on alone are reported too.
+    fn validate(&self) -> ValidationReport {
+        // nothing cancels it, so it always finishes.
+        self.validate_with(&mut |_, _| {}, &CancelToken::new())
+            .unwrap_or_default()
+    }
+
+    // validate_with is validate for big lattices: progress is called with
+    // (LatNodes reached so far, LatNode count) as each one is checked, and
+    // the check is abandoned with Cancelled once cancel is cancelled.
+    fn validate_with(
+        &self,
+        progress: &mut dyn FnMut(usize, usize),
+        cancel: &CancelToken,
+    ) -> Result<ValidationReport, LatticeError> {
+        let total = self.read_pending().len() + self.read_fulfilled().len();
+        let mut done = 0;
+        let mut problems = Vec::new();
+        let held =
+            |k: &str| self.read_node(k).is_some() || self.read_placeholders().contains_key(k);
+
+        for k in self.read_pending().keys() {
+            if self.read_fulfilled().contains_key(k) {
+                problems.push(Inconsistency::PendingAndFulfilled(k.clone()));
+            }
+        }
+
+        for (k, t) in self
+            .read_pending()
+            .iter()
+            .chain(self.read_fulfilled().iter())
+        {
+            if cancel.is_cancelled() {
+                return Err(LatticeError::Cancelled);
+            }
+            done += 1;
+            progress(done, total);
+
+            let relations = t
+                .depends_on()
+                .keys()
+                .chain(t.required_by().keys())
+                .chain(t.fulfilled_by().keys());
+            for r in relations {
+                if !held(r) {
+                    problems.push(Inconsistency::Dangling {
+                        node: k.clone(),
+                        reference: r.clone(),
+                    });
+                }
+            }
+
+            for d in t.depends_on().keys().chain(t.fulfilled_by().keys()) {
+                let recorded = match self.read_node(d) {
+                    Some(x) => x.required_by().contains_key(k),
+                    None => match self.read_placeholders().get(d) {
+                        Some(p) => p.required_by.contains_key(k),
+                        None => true,
+                    },
+                };
+                if !recorded {
+                    problems.push(Inconsistency::MissingRequiredBy {
+                        node: k.clone(),
+                        dep: d.clone(),
+                    });
+                }
+            }
+
+            for r in t.required_by().keys() {
+                let recorded = match self.read_node(r) {
+                    Some(x) => x.depends_on().contains_key(k) || x.fulfilled_by().contains_key(k),
+                    None => match self.read_placeholders().get(r) {
+                        Some(p) => p.depends_on.contains_key(k) || p.fulfilled_by.contains_key(k),
+                        None => true,
+                    },
+                };
+                if !recorded {
+                    problems.push(Inconsistency::MissingDependsOn {
+                        node: k.clone(),
+                        dependent: r.clone(),
+                    });
+                }
+            }
+        }
+
+        for (k, t) in self.read_fulfilled() {
+            if t.is_blocked() {
+                problems.push(Inconsistency::FulfilledWhileWaiting {
+                    node: k.clone(),
+                    depends_on: t.waiting_on().into_iter().cloned().collect(),
+                });
+            }
+        }
+
+        problems.sort();
+        problems.dedup();
+        Ok(ValidationReport { problems })
+    }
+
+    // health checks the lattice over in one pass, for readiness probes and
+    // dashboards: cycles, unresolved edges, stuck LatNodes, ready LatNodes
+    // the Limits would refuse to fulfill and validate's inconsistencies.
+    fn health(&self) -> Health {
+        let pending = self.read_pending();
+
+        // Unblock pending LatNodes from the ready ones outwards, whatever
+        // is never reached is stuck. A quorum lets a LatNode through once
+        // enough of its members are reached, soft edges never hold it.
+        let mut waiting_on = HashMap::<&str, Vec<&str>>::new();
+        let mut stack = Vec::new();
+        for (k, t) in pending {
+            if !t.is_blocked() {
+                stack.push(k.as_str());
+            }
+            for d in t.depends_on().keys() {
+                waiting_on.entry(d).or_default().push(k);
+            }
+        }
+        let ready = stack.clone();
+        let mut past = HashMap::<String, ()>::new();
+        let mut reached: HashMap<&str, ()> = stack.iter().map(|k| (*k, ())).collect();
+        while let Some(k) = stack.pop() {
+            past.insert(k.to_string(), ());
+            for r in waiting_on.get(k).into_iter().flatten() {
+                if reached.contains_key(r) {
+                    continue;
+                }
+                let t = &pending[*r];
+                if !blocked_past(t.depends_on(), t.fulfilled_by(), t.quorums(), &past) {
+                    reached.insert(r, ());
+                    stack.push(r);
+                }
+            }
+        }
+        let mut stuck: Vec<String> = pending
+            .keys()
+            .filter(|k| !reached.contains_key(k.as_str()))
+            .cloned()
+            .collect();
+        stuck.sort();
+
+        let limits = self.read_limits();
+        let mut over_limits = Vec::new();
+        if limits.max_cascade_size.is_some() || limits.max_cascade_depth.is_some() {
+            over_limits = ready
+                .into_iter()
+                .filter(|k| self.check_limits(k).is_err())
+                .map(|k| k.to_string())
+                .collect();
+            over_limits.sort();
+        }
+
+        Health {
+            pending: pending.len(),
+            fulfilled: self.read_fulfilled().len(),
+            placeholders: self.read_placeholders().len(),
+            frozen: self.is_frozen(),
+            cycles: self.detect_cycles(),
+            unresolved: self.unresolved(),
+            stuck,
+            over_limits,
+            inconsistencies: self.validate().problems,
+        }
+    }
+
+    // unresolved lists every (LatNode, key) where the LatNode holds an edge
+    // or reference to a key the lattice doesn't, sorted.
+    fn unresolved(&self) -> Vec<(String, String)> {
+        let mut out = Vec::new();
+        for (k, t) in self
+            .read_pending()
+            .iter()
+            .chain(self.read_fulfilled().iter())
+        {
+            let relations = t
+                .depends_on()
+                .keys()
+                .chain(t.required_by().keys())
+                .chain(t.fulfilled_by().keys())
+                .chain(t.references().keys());
+            for r in relations {
+                if self.read_node(r).is_none() {
+                    out.push((k.clone(), r.clone()));
+                }
+            }
+        }
+        out.sort();
+        out.dedup();
+        out
+    }
+
+    // keys edges were made to before any LatNode with that key was
+    // appended, see Placeholder.
+    fn read_placeholders(&self) -> &HashMap<String, Placeholder>;
+    fn get_placeholders(&mut self) -> &mut HashMap<String, Placeholder>;
+
+    // LatNodes fulfilled by force_fulfill, key -> the dependencies it
+    // skipped, sorted.
+    fn read_forced(&self) -> &HashMap<String, Vec<String>>;
+    fn get_forced(&mut self) -> &mut HashMap<String, Vec<String>>;
+
+    // pending LatNodes parked by fail, key -> the reason given.
+    fn read_failed(&self) -> &HashMap<String, String>;
+    fn get_failed(&mut self) -> &mut HashMap<String, String>;
+
+    // the ID of the run this lattice belongs to, recorded in the Origin of
+    // every LatNode it does the work of, and with that work in its cache.
+    fn read_run_id(&self) -> &Option<String>;
+    fn get_run_id(&mut self) -> &mut Option<String>;
+
+    // consulted before every change, see permit.
+    fn read_policy(&self) -> &Option<Box<dyn MutationPolicy>>;
+    fn get_policy(&mut self) -> &mut Option<Box<dyn MutationPolicy>>;
+    // who changes are being made as, for the MutationPolicy.
+    fn read_actor(&self) -> &Option<String>;
+    fn get_actor(&mut self) -> &mut Option<String>;
+
+    // the FulfillmentCache fulfillments are stored in and ready LatNodes
+    // looked up in, None (the default) for no caching.
+    fn read_cache(&self) -> &Option<Box<dyn FulfillmentCache>>;
+    fn get_cache(&mut self) -> &mut Option<Box<dyn FulfillmentCache>>;
+
+    fn read_change_gate(&self) -> &ChangeGate;
+    fn get_change_gate(&mut self) -> &mut ChangeGate;
+
+    // in_progress reports whether key is fulfilled or has had any of its
+    // dependencies fulfilled.
+    fn in_progress(&self, key: &str) -> bool {
+        match self.read_pending().get(key) {
+            Some(t) => !t.fulfilled_by().is_empty(),
+            None => self.read_fulfilled().contains_key(key),
+        }
+    }
+
+    // approve decides whether change may go ahead: always, unless the
+    // MutationPolicy refuses it, the lattice is frozen or the ChangeGate
+    // wants approval for touching a LatNode in progress, in which case
+    // only a token the gate accepts lets it through.
+    fn approve(
+        &self,
+        change: &StructuralChange,
+        token: Option<&ApprovalToken>,
+    ) -> Result<(), LatticeError> {
+        self.permit(&Mutation::Structural(*change))?;
+        let gated = match self.read_change_gate() {
+            ChangeGate::Open => false,
+            ChangeGate::Approval(_) => match change {
+                StructuralChange::Append(_) => false,
+                StructuralChange::Replace(key) | StructuralChange::Remove(key) => {
+                    self.in_progress(key)
+                }
+                StructuralChange::AddEdge {
+                    requires,
+                    is_required,
+                }
+                | StructuralChange::RemoveEdge {
+                    requires,
+                    is_required,
+                } => self.in_progress(requires) || self.in_progress(is_required),
+                StructuralChange::AddReference { from, to }
+                | StructuralChange::RemoveReference { from, to } => {
+                    self.in_progress(from) || self.in_progress(to)
+                }
+                StructuralChange::Rename { from, .. } => self.in_progress(from),
+            },
+        };
+        if !gated && !self.is_frozen() {
+            return Ok(());
+        }
+
+        match (self.read_change_gate(), token) {
+            (ChangeGate::Approval(f), Some(token)) if f(change, token) => Ok(()),
+            _ if self.is_frozen() => Err(LatticeError::Frozen),
+            _ => Err(LatticeError::NeedsApproval),
+        }
+    }
+
+    // permit asks the MutationPolicy, if the lattice has one, whether the
+    // actor may make mutation. Err with Denied and its reason if not.
+    fn permit(&self, mutation: &Mutation) -> Result<(), LatticeError> {
+        match self.read_policy() {
+            None => Ok(()),
+            Some(p) => p
+                .check(self.read_actor().as_deref(), mutation)
+                .map_err(LatticeError::Denied),
+        }
+    }
+
+    // handle returns the NodeHandle for key, None if key was never
+    // appended.
+    fn handle(&self, key: &str) -> Option<NodeHandle> {
+        self.read_handles().handle(self.canonical(key))
+    }
+
+    // key_of returns the key h stands in for, StaleHandle once that
+    // LatNode has been removed.
+    fn key_of(&self, h: NodeHandle) -> Result<&str, StaleHandle> {
+        self.read_handles().key(h)
+    }
+
+    // node_by_handle is read_node by handle.
+    fn node_by_handle(&self, h: NodeHandle) -> Result<&T, StaleHandle> {
+        self.read_node(self.key_of(h)?).ok_or(StaleHandle(h))
+    }
+
+    // fulfill_by_handle is fulfill by handle, Err for a stale handle too.
+    fn fulfill_by_handle(&mut self, h: NodeHandle) -> Result<(), LatticeError> {
+        let key = self.key_of(h)?.to_string();
+        self.fulfill(key)
+    }
+
+    // fulfill_from fulfills key and cascades, recording trigger as the
+    // LatNode that caused it. The cascade runs off an explicit stack, depth
+    // first, so chains of any length are safe. step is called after each
+    // LatNode is fulfilled, returning false stops the cascade there with
+    // Cancelled, leaving the LatNodes it hadn't reached pending.
+    fn fulfill_from(
+        &mut self,
+        key: String,
+        trigger: Option<String>,
+        step: &mut dyn FnMut() -> bool,
+    ) -> Result<(), LatticeError> {
+        match self.read_pending().get(&key) {
+            None => return Err(not_pending(self, key)),
+            Some(_) if self.read_failed().contains_key(&key) => {
+                return Err(LatticeError::Failed(key))
+            }
+            Some(t) if t.skipped().is_some() => return Err(LatticeError::Skipped(key)),
+            Some(_) => {}
+        }
+        self.permit(&Mutation::Fulfill(&key))?;
+
+        // (key, trigger), every key on it is still pending.
+        let mut stack = vec![(key, trigger)];
+        // Chain fast path: a LatNode with a single dependent that cascades
+        // hands it straight to the next turn, already out of pending, with
+        // nothing queued or cloned for it.
+        let mut next = None;
+        let caching = self.read_cache().is_some();
+        // cache entries for the LatNodes fulfilled, stored once the
+        // cascade is over rather than a round trip at a time inside it.
+        let mut stores = Vec::new();
+        loop {
+            let (key, mut target, trigger) = match next.take() {
+                Some(link) => link,
+                None => match stack.pop() {
+                    None => break,
+                    Some((key, trigger)) => match self.get_pending().remove_entry(&key) {
+                        None => continue,
+                        Some((key, target)) => (key, target, trigger),
+                    },
+                },
+            };
+
+            let mut cascade = Vec::<String>::new();
+            let mut settled = Vec::<String>::new();
+            let mut unlocked = Vec::<String>::new();
+            let is_link = target.required_by().len() == 1;
+            let mut link = false;
+            let pending = self.get_pending();
+            for k in target.required_by().keys() {
+                match pending.get_mut(k) {
+                    // The dependent was fulfilled explicitly while still
+                    // waiting on this LatNode, or is a placeholder, settle
+                    // it once key is moved.
+                    None => settled.push(k.clone()),
+                    Some(x) => {
+                        // a dependent that doesn't wait on key has nothing
+                        // to do.
+                        if x.depend_fulfilled(key.clone()).is_err() {
+                            continue;
+                        }
+                        if !x.is_pending() && x.skipped().is_none() {
+                            cascade_log!(
+                                node = k.as_str(), trigger = key.as_str(), decision = "cascade";
+                                "{} cascades to fulfilled because {} fulfilled", k, key
+                            );
+                            match is_link {
+                                true => link = true,
+                                false => cascade.push(k.clone()),
+                            }
+                        } else if !x.is_blocked() {
+                            cascade_log!(
+                                node = k.as_str(), trigger = key.as_str(), decision = "unlocked";
+                                "{} unlocked because {} fulfilled", k, key
+                            );
+                            if caching {
+                                unlocked.push(k.clone());
+                            }
+                        } else {
+                            cascade_log!(
+                                node = k.as_str(), trigger = key.as_str(), decision = "blocked",
+                                blocked_on = blocked_on(x).as_str();
+                                "{} still blocked on {{{}}}", k, blocked_on(x)
+                            );
+                        }
+                    }
+                };
+            }
+
+            for k in settled {
+                match self.get_fulfilled().get_mut(&k) {
+                    Some(x) => {
+                        let _ = x.depend_fulfilled(key.clone());
+                    }
+                    None => {
+                        if let Some(p) = self.get_placeholders().get_mut(&k) {
+                            if p.depends_on.remove(&key).is_some() {
+                                p.fulfilled_by.insert(key.clone(), ());
+                            }
+                        }
+                    }
+                }
+            }
+
+            let link = match link {
+                false => None,
+                true => {
+                    let k = target.required_by().keys().next().unwrap();
+                    match self.read_failed().contains_key(k) {
+                        true => None,
+                        false => self.get_pending().remove_entry(k),
+                    }
+                }
+            };
+
+            // only a cache hit reaches here with an Origin, see take_entry
+            // and append_pending.
+            let hit = target.origin().is_some();
+            let origin = match target.origin() {
+                Some(o) => o.clone(),
+                None => Origin {
+                    run: self.read_run_id().clone(),
+                    kind: OriginKind::Local,
+                },
+            };
+            for a in target.outputs() {
+                put_artifact(target.get_artifacts(), a);
+            }
+            if caching {
+                if let Some(ck) = cache_key(self, &target) {
+                    let entry = CacheEntry {
+                        artifacts: target.artifacts().to_vec(),
+                        run: origin.run.clone(),
+                    };
+                    stores.push((ck, entry));
+                }
+            }
+            target.set_provenance(Some(match &trigger {
+                _ if hit => Provenance::Cached,
+                None => Provenance::Explicit,
+                Some(t) => Provenance::Cascade(t.clone()),
+            }));
+            target.set_origin(Some(origin.clone()));
+            self.get_fulfilled().insert(key.clone(), target);
+            self.get_fulfillment_log().push(Fulfillment {
+                key: key.clone(),
+                triggered_by: trigger,
+                origin,
+            });
+
+            if !step() {
+                if let Some((k, t)) = link {
+                    self.get_pending().insert(k, t);
+                }
+                // hits still on the stack go back to plain pending.
+                for (k, _) in stack {
+                    if let Some(t) = self.get_pending().get_mut(&k) {
+                        t.set_origin(None);
+                    }
+                }
+                store_entries(self, stores);
+                return Err(LatticeError::Cancelled);
+            }
+
+            if let Some((k, t)) = link {
+                next = Some((k, t, Some(key)));
+                continue;
+            }
+
+            // reversed, so the first dependent is fulfilled (with its own
+            // cascade) first.
+            for k in unlocked {
+                if let Some(entry) = cached_entry(self, &k) {
+                    cache_log!(node = k.as_str(), trigger = key.as_str(), decision = "hit";
+                        "{} found in the build cache once {} fulfilled", k, key);
+                    take_entry(self.get_pending().get_mut(&k).unwrap(), entry);
+                    cascade.push(k);
+                }
+            }
+            for k in cascade.into_iter().rev() {
+                if !self.read_failed().contains_key(&k) {
+                    stack.push((k, Some(key.clone())));
+                }
+            }
+        }
+
+        store_entries(self, stores);
+        Ok(())
+    }
+
+    // check_limits fails if fulfilling key would break the lattice's Limits.
+    fn check_limits(&self, key: &str) -> Result<(), LatticeError> {
+        let limits = self.read_limits();
+        if limits.max_cascade_size.is_some() || limits.max_cascade_depth.is_some() {
+            let (size, depth) = self.cascade_extent(key);
+            if limits.max_cascade_size.is_some_and(|max| size > max)
+                || limits.max_cascade_depth.is_some_and(|max| depth > max)
+            {
+                return Err(LatticeError::CascadeLimit {
+                    key: key.to_string(),
+                    size,
+                    depth,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Always public below here:
+
+    // fulfill moves key to fulfilled and cascades to every dependent it
+    // leaves completed with nothing to wait on. Err with Blocked if key
+    // still waits on a dependency, force_fulfill overrides that.
+    fn fulfill(&mut self, key: String) -> Result<(), LatticeError> {
+        let key = resolved(self, key);
+        unblocked(self, &key)?;
+        self.check_limits(&key)?;
+        self.fulfill_from(key, None, &mut || true)
+    }
+
+    // fulfill_with is fulfill for big cascades: progress is called with
+    // (LatNodes fulfilled so far, cascade size) after each one, and the
+    // cascade stops once cancel is cancelled. A stopped cascade returns
+    // Cancelled, leaving the LatNodes it hadn't reached pending, ready to be
+    // fulfilled individually.
+    fn fulfill_with(
+        &mut self,
+        key: String,
+        progress: &mut dyn FnMut(usize, usize),
+        cancel: &CancelToken,
+    ) -> Result<(), LatticeError> {
+        let key = resolved(self, key);
+        unblocked(self, &key)?;
+        self.check_limits(&key)?;
+        if cancel.is_cancelled() {
+            return Err(LatticeError::Cancelled);
+        }
+
+        let (total, _) = self.cascade_extent(&key);
+        let mut done = 0;
+        self.fulfill_from(key, None, &mut || {
+            done += 1;
+            progress(done, total);
+            !cancel.is_cancelled()
+        })
+    }
+
+    // force_fulfill is fulfill for an operator overriding the lattice,
+    // returning the dependencies key was still waiting on, sorted. Those
+    // are kept in read_forced until key is unfulfilled or reset, which
+    // reverses the override. The MutationPolicy is asked about a
+    // ForceFulfill before the Fulfill.
+    fn force_fulfill(&mut self, key: String) -> Result<Vec<String>, LatticeError> {
+        let key = resolved(self, key);
+        let skipped = match self.read_pending().get(&key) {
+            None => return Err(not_pending(self, key)),
+            Some(t) => {
+                let mut skipped: Vec<String> = t.depends_on().keys().cloned().collect();
+                skipped.sort();
+                skipped
+            }
+        };
+        self.permit(&Mutation::ForceFulfill(&key))?;
+        self.check_limits(&key)?;
+        self.fulfill_from(key.clone(), None, &mut || true)?;
+        if !skipped.is_empty() {
+            self.get_forced().insert(key, skipped.clone());
+        }
+        Ok(skipped)
+    }
+
+    // skip takes a pending LatNode out of the work, its dependents treating
+    // it as policy says. Satisfies fulfills key, cascading as fulfill
+    // would, reset reverses it. Blocks leaves key pending until unskip.
+    // Either way ReadNode::skipped reports the policy.
+    fn skip(&mut self, key: String, policy: SkipPolicy) -> Result<(), LatticeError> {
+        let key = resolved(self, key);
+        match self.read_pending().get(&key) {
+            None => return Err(not_pending(self, key)),
+            Some(t) if t.skipped().is_some() => return Err(LatticeError::Skipped(key)),
+            Some(_) => {}
+        }
+        self.permit(&Mutation::Skip(&key))?;
+
+        match policy {
+            SkipPolicy::Satisfies => {
+                self.check_limits(&key)?;
+                self.fulfill_from(key.clone(), None, &mut || true)?;
+                if let Some(t) = self.get_fulfilled().get_mut(&key) {
+                    t.set_skipped(Some(policy));
+                }
+            }
+            SkipPolicy::Blocks => {
+                if let Some(t) = self.get_pending().get_mut(&key) {
+                    t.set_skipped(Some(policy));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // unskip returns a LatNode skipped with SkipPolicy::Blocks to the work.
+    // It stays pending, fulfill it (or let a cascade) as usual.
+    fn unskip(&mut self, key: String) -> Result<(), LatticeError> {
+        let key = resolved(self, key);
+        if self
+            .read_pending()
+            .get(&key)
+            .is_some_and(|t| t.skipped().is_some())
+        {
+            self.permit(&Mutation::Unskip(&key))?;
+        }
+        match self.get_pending().get_mut(&key) {
+            Some(t) if t.skipped().is_some() => {
+                t.set_skipped(None);
+                Ok(())
+            }
+            _ => Err(LatticeError::NotSkipped(key)),
+        }
+    }
+
+    // fail parks a pending LatNode that couldn't be done, keeping reason in
+    // read_failed. It stays pending but won't be fulfilled, by call or
+    // cascade, until recover. Returns its blast radius.
+    fn fail(&mut self, key: String, reason: String) -> Result<Vec<String>, LatticeError> {
+        let key = resolved(self, key);
+        if !self.read_pending().contains_key(&key) {
+            return Err(not_pending(self, key));
+        }
+        self.permit(&Mutation::Fail(&key))?;
+        self.get_failed().insert(key.clone(), reason);
+        Ok(self.blast_radius(&key))
+    }
+
+    // recover clears key's failure, leaving it pending as it was.
+    fn recover(&mut self, key: String) -> Result<(), LatticeError> {
+        let key = resolved(self, key);
+        if self.read_failed().contains_key(&key) {
+            self.permit(&Mutation::Recover(&key))?;
+        }
+        match self.get_failed().remove(&key) {
+            Some(_) => Ok(()),
+            None => Err(LatticeError::NotFailed(key)),
+        }
+    }
+
+    // failures lists every failed LatNode with its reason, sorted by key.
+    fn failures(&self) -> Vec<(String, String)> {
+        let mut out: Vec<(String, String)> = self
+            .read_failed()
+            .iter()
+            .map(|(k, r)| (k.clone(), r.clone()))
+            .collect();
+        out.sort();
+        out
+    }
+
+    // blast_radius lists the pending LatNodes waiting on key, directly or
+    // through others, sorted: all BlockedByFailure while key is failed.
+    // Soft edges and edges into a met quorum don't count.
+    fn blast_radius(&self, key: &str) -> Vec<String> {
+        let pending = self.read_pending();
+        let mut seen = HashMap::<&str, ()>::new();
+        let mut stack = vec![self.canonical(key)];
+        while let Some(k) = stack.pop() {
+            let t = match pending.get(k) {
+                None => continue,
+                Some(t) => t,
+            };
+            for r in t.required_by().keys() {
+                let waits = pending
+                    .get(r)
+                    .is_some_and(|x| x.depends_on().contains_key(k) && !spared(x, k));
+                if waits && seen.insert(r, ()).is_none() {
+                    stack.push(r);
+                }
+            }
+        }
+
+        let mut out: Vec<String> = seen.into_keys().map(|k| k.to_string()).collect();
+        out.sort();
+        out
+    }
+
+    // failed_upstream lists the failed LatNodes key waits on, directly or
+    // through others, sorted. Only edges in waiting_on are followed.
+    fn failed_upstream(&self, key: &str) -> Vec<String> {
+        let failed = self.read_failed();
+        if failed.is_empty() {
+            return Vec::new();
+        }
+
+        let pending = self.read_pending();
+        let mut seen = HashMap::<&str, ()>::new();
+        let mut out = Vec::new();
+        let mut stack = vec![self.canonical(key)];
+        while let Some(k) = stack.pop() {
+            for d in pending.get(k).into_iter().flat_map(|t| t.waiting_on()) {
+                if seen.insert(d, ()).is_some() {
+                    continue;
+                }
+                if failed.contains_key(d) {
+                    out.push(d.clone());
+                }
+                stack.push(d);
+            }
+        }
+        out.sort();
+        out
+    }
+
+    // fulfill_step fulfills key alone, without cascading: dependents it
+    // unblocks stay pending and are returned, sorted, for the caller to
+    // fulfill (or not) as it sees fit. fulfill remains the auto cascading
+    // default.
+    fn fulfill_step(&mut self, key: String) -> Result<Vec<String>, LatticeError> {
+        let key = resolved(self, key);
+        unblocked(self, &key)?;
+        let mut stopped = false;
+        self.fulfill_from(key.clone(), None, &mut || {
+            stopped = true;
+            false
+        })
+        .or_else(|e| if stopped { Ok(()) } else { Err(e) })?;
+
+        let pending = self.read_pending();
+        let mut unblocked: Vec<String> = match self.read_fulfilled().get(&key) {
+            None => return Err(LatticeError::UnknownKey(key)),
+            Some(t) => t
+                .required_by()
+                .keys()
+                .filter(|k| pending.get(*k).is_some_and(|x| !x.is_blocked()))
+                .cloned()
+                .collect(),
+        };
+        unblocked.sort();
+        Ok(unblocked)
+    }
+
+    // cascade_extent works out, without changing anything, how many
+    // LatNodes fulfilling key would move to fulfilled (key included) and
+    // the longest chain of cascades it would set off. (0, 0) if key isn't
+    // pending.
+    fn cascade_extent(&self, key: &str) -> (usize, usize) {
+        let pending = self.read_pending();
+        if !pending.contains_key(key) {
+            return (0, 0);
+        }
+
+        // past holds the keys the cascade has fulfilled so far.
+        let mut past = HashMap::<String, ()>::new();
+        let mut queued = HashMap::<&str, ()>::from([(key, ())]);
+        let mut stack = vec![(key, 1)];
+        let (mut size, mut depth) = (0, 0);
+        while let Some((k, d)) = stack.pop() {
+            size += 1;
+            depth = depth.max(d);
+            past.insert(k.to_string(), ());
+
+            for r in pending[k].required_by().keys() {
+                if let Some(x) = pending.get(r) {
+                    if queued.contains_key(r.as_str()) || !x.depends_on().contains_key(k) {
+                        continue;
+                    }
+
+                    if !blocked_past(x.depends_on(), x.fulfilled_by(), x.quorums(), &past)
+                        && x.is_completed()
+                        && x.skipped().is_none()
+                        && !self.read_failed().contains_key(r)
+                    {
+                        queued.insert(r.as_str(), ());
+                        stack.push((r.as_str(), d + 1));
+                    }
+                }
+            }
+        }
+
+        (size, depth)
+    }
+
+    // fulfillment_order returns every fulfillment so far in the order it
+    // happened, each noting which fulfillment cascaded into it.
+    fn fulfillment_order(&self) -> &[Fulfillment] {
+        self.read_fulfillment_log()
+    }
+
+    // export_fulfillment_order renders fulfillment_order as tab separated
+    // lines of sequence number, key, triggering key (empty when the
+    // LatNode was fulfilled directly), origin (local, cache or replica)
+    // and origin run ID (empty when unknown), under a header line.
+    fn export_fulfillment_order(&self) -> String {
+        let mut out = String::from("seq\tkey\ttriggered_by\torigin\trun\n");
+        for (i, f) in self.read_fulfillment_log().iter().enumerate() {
+            let trigger = f.triggered_by.as_deref().unwrap_or("");
+            let kind = match f.origin.kind {
+                OriginKind::Local => "local",
+                OriginKind::Cache => "cache",
+                OriginKind::Replica => "replica",
+            };
+            let run = f.origin.run.as_deref().unwrap_or("");
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                i + 1,
+                f.key,
+                trigger,
+                kind,
+                run
+            ));
+        }
+        out
+    }
+
+    // quorum_satisfiers lists each met Quorum of key's LatNode with the
+    // members that met it: the first need of them to be fulfilled, by
+    // fulfillment_order. Members fulfilled without a log entry, loaded
+    // fulfilled say, come first, by key. Soft edges aren't listed.
+    fn quorum_satisfiers(&self, key: &str) -> Vec<(Quorum, Vec<String>)> {
+        let t = match self.read_node(self.canonical(key)) {
+            None => return Vec::new(),
+            Some(t) => t,
+        };
+        let seq: HashMap<&str, usize> = self
+            .fulfillment_order()
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (f.key.as_str(), i + 1))
+            .collect();
+
+        t.quorums()
+            .iter()
+            .filter(|q| q.need > 0 && q.is_met(t.fulfilled_by()))
+            .map(|q| {
+                let mut done: Vec<&String> = q
+                    .members
+                    .iter()
+                    .filter(|m| t.fulfilled_by().contains_key(*m))
+                    .collect();
+                done.sort_by_key(|m| (seq.get(m.as_str()).copied().unwrap_or(0), *m));
+                let by = done.into_iter().take(q.need).cloned().collect();
+                (q.clone(), by)
+            })
+            .collect()
+    }
+
+    // node_state returns the state of key, None if the lattice doesn't
+    // hold it.
+    fn node_state(&self, key: &str) -> Option<NodeState> {
+        let key = self.canonical(key);
+        match self.read_pending().get(key) {
+            Some(_) if self.read_failed().contains_key(key) => Some(NodeState::Failed),
+            Some(t) if t.skipped().is_some() => Some(NodeState::Skipped),
+            Some(t) if !t.is_blocked() => Some(NodeState::Ready),
+            Some(_) if !self.failed_upstream(key).is_empty() => Some(NodeState::BlockedByFailure),
+            Some(_) => Some(NodeState::Blocked),
+            None if self.read_fulfilled().contains_key(key) => Some(NodeState::Fulfilled),
+            None => self
+                .read_placeholders()
+                .get(key)
+                .map(|_| NodeState::Placeholder),
+        }
+    }
+
+    // attach_artifact records a on key, pending or fulfilled, replacing
+    // any artifact of the same name. Artifacts are dropped when key is
+    // reset.
+    fn attach_artifact(&mut self, key: String, a: Artifact) -> Result<(), LatticeError> {
+        let key = resolved(self, key);
+        if self.read_node(&key).is_some() {
+            self.permit(&Mutation::AttachArtifact(&key))?;
+        }
+        let t = match self.get_pending().get_mut(&key) {
+            Some(t) => t,
+            None => match self.get_fulfilled().get_mut(&key) {
+                Some(t) => t,
+                None => return Err(LatticeError::UnknownKey(key)),
+            },
+        };
+        put_artifact(t.get_artifacts(), a);
+        Ok(())
+    }
+
+    // apply_cache fulfills every ready LatNode whose work the cache holds,
+    // and whatever that unlocks in turn. Appending checks the cache by
+    // itself, this is for lattices built before it was set, say by
+    // from_node_list. Returns the LatNodes fulfilled from the cache, in
+    // order.
+    fn apply_cache(&mut self) -> Vec<String> {
+        let from = self.read_fulfillment_log().len();
+        let mut ready: Vec<String> = self
+            .read_pending()
+            .iter()
+            .filter(|(_, t)| !t.is_blocked())
+            .map(|(k, _)| k.clone())
+            .collect();
+        ready.sort();
+        for k in ready {
+            cache_hit(self, &k);
+        }
+
+        self.read_fulfillment_log()[from..]
+            .iter()
+            .filter(|f| {
+                self.read_fulfilled()
+                    .get(&f.key)
+                    .and_then(|t| t.provenance())
+                    == Some(&Provenance::Cached)
+            })
+            .map(|f| f.key.clone())
+            .collect()
+    }
+
+    // input_artifacts lists the artifacts of everything key depends on,
+    // done or not, as (producer, artifact) sorted by producer then name.
+    fn input_artifacts(&self, key: &str) -> Result<Vec<(String, Artifact)>, LatticeError> {
+        let key = self.canonical(key);
+        let t = match self.read_node(key) {
+            None => return Err(LatticeError::UnknownKey(key.to_string())),
+            Some(t) => t,
+        };
+
+        let mut out: Vec<(String, Artifact)> = t
+            .depends_on()
+            .keys()
+            .chain(t.fulfilled_by().keys())
+            .filter_map(|d| self.read_node(d).map(|x| (d, x)))
+            .flat_map(|(d, x)| x.artifacts().iter().map(move |a| (d.clone(), a.clone())))
+            .collect();
+        out.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.name.cmp(&b.1.name)));
+        Ok(out)
+    }
+
+    // export_state lists the key and state of every LatNode filter
+    // matches, sorted by key, without cloning any node data. Meant for
+    // reconciling against external systems where a full copy is too heavy.
+    fn export_state(&self, filter: StateFilter) -> Vec<StateRecord> {
+        let mut seq = HashMap::<&str, usize>::new();
+        for (i, f) in self.read_fulfillment_log().iter().enumerate() {
+            seq.insert(f.key.as_str(), i + 1);
+        }
+
+        let mut out: Vec<StateRecord> = self
+            .read_pending()
+            .keys()
+            .chain(self.read_fulfilled().keys())
+            .chain(self.read_placeholders().keys())
+            .filter_map(|k| {
+                let state = self.node_state(k)?;
+                if !filter.matches(state) {
+                    return None;
+                }
+                let fulfilled_seq = match state {
+                    NodeState::Fulfilled => seq.get(k.as_str()).copied(),
+                    _ => None,
+                };
+                let (artifacts, origin) = match self.read_node(k) {
+                    Some(t) => (t.artifacts().to_vec(), t.origin().cloned()),
+                    None => (Vec::new(), None),
+                };
+                Some(StateRecord {
+                    key: k.clone(),
+                    state,
+                    fulfilled_seq,
+                    artifacts,
+                    origin,
+                })
+            })
+            .collect();
+        out.sort_by(|a, b| a.key.cmp(&b.key));
+        out
+    }
+
+    // unfulfill moves key back to pending, puts it back in its dependents'
+    // depends_on maps, and does the same for every fulfilled dependent
+    // downstream, since their fulfillment relied on it. Err if key isn't
+    // fulfilled.
+    fn unfulfill(&mut self, key: String) -> Result<(), LatticeError> {
+        self.reset(key).map(|_| ())
+    }
+
+    // reset is unfulfill for retry workflows, returning every key moved
+    // back to pending, key itself included, sorted. Each is waiting again
+    // on whatever its fulfilled_by held from the reset, ready to be
+    // fulfilled anew.
+    fn reset(&mut self, key: String) -> Result<Vec<String>, LatticeError> {
+        let key = resolved(self, key);
+        if !self.read_fulfilled().contains_key(&key) {
+            return Err(match self.read_pending().contains_key(&key) {
+                true => LatticeError::NotFulfilled(key),
+                false => LatticeError::UnknownKey(key),
+            });
+        }
+        self.permit(&Mutation::Reset(&key))?;
+
+        let mut reset = Vec::new();
+        let mut queue = vec![key];
+        while let Some(key) = queue.pop() {
+            // already reverted through another path.
+            let mut t = match self.get_fulfilled().remove(&key) {
+                None => continue,
+                Some(t) => t,
+            };
+            t.set_provenance(None);
+            t.set_origin(None);
+            t.set_skipped(None);
+            t.get_artifacts().clear();
+
+            for k in t.required_by().keys() {
+                if let Some(x) = self.get_pending().get_mut(k) {
+                    let _ = x.depend_unfulfilled(key.clone());
+                } else if let Some(x) = self.get_fulfilled().get_mut(k) {
+                    // one whose Quorum is still met without key stays
+                    // fulfilled.
+                    if x.depend_unfulfilled(key.clone()).is_ok() && x.is_blocked() {
+                        queue.push(k.clone());
+                    }
+                } else if let Some(p) = self.get_placeholders().get_mut(k) {
+                    if p.fulfilled_by.remove(&key).is_some() {
+                        p.depends_on.insert(key.clone(), ());
+                    }
+                }
+            }
+
+            self.get_forced().remove(&key);
+            reset.push(key.clone());
+            self.get_pending().insert(key, t);
+        }
+
+        reset.sort();
+        Ok(reset)
+    }
+
+    fn update_value(&mut self, key: String, update: U) -> Result<(), LatticeError> {
+        let key = resolved(self, key);
+        if self.read_pending().contains_key(&key) {
+            self.permit(&Mutation::Update(&key))?;
+        }
+        let m = self.get_pending();
+
+        let x = m.get_mut(&key);
+        match x {
+            None => Err(not_pending(self, key)),
+            Some(t) => {
+                t.update(update)?;
+                if !t.is_pending() && self.should_auto_fulfill(&key) {
+                    return self.fulfill(key);
+                };
+                Ok(())
+            }
+        }
+    }
+
+    // should_auto_fulfill applies the LatNode's override, or else the
+    // lattice's FulfillPolicy, to a pending LatNode.
+    fn should_auto_fulfill(&self, key: &str) -> bool {
+        let t = match self.read_pending().get(key) {
+            None => return false,
+            Some(t) => t,
+        };
+
+        match t.auto_fulfill() {
+            Some(auto) => auto,
+            None => match self.read_fulfill_policy() {
+                FulfillPolicy::Auto => true,
+                FulfillPolicy::Manual => false,
+                FulfillPolicy::Confirm(f) => f(t),
+            },
+        }
+    }
+
+    // boolean indicates whether this relationship blocks the value at is_required_by
+    fn update_required_by(
+        &mut self,
+        target: String,
+        is_required_by: String,
+    ) -> Result<bool, LatticeError> {
+        let (target, is_required_by) = (resolved(self, target), resolved(self, is_required_by));
+        self.approve(
+            &StructuralChange::AddEdge {
+                requires: &is_required_by,
+                is_required: &target,
+            },
+            None,
+        )?;
+        if target == is_required_by {
+            return Err(LatticeError::SelfDependency(target));
+        }
+        self.check_known(Some(&is_required_by))?;
+        if self.read_node(&is_required_by).is_none() {
+            let waiting = match self.node_state(&target) {
+                None | Some(NodeState::Placeholder) => {
+                    return Err(LatticeError::UnknownKey(target))
+                }
+                Some(state) => state != NodeState::Fulfilled,
+            };
+            let p = self
+                .get_placeholders()
+                .entry(is_required_by.clone())
+                .or_default();
+            if waiting {
+                p.depends_on.insert(target.clone(), ());
+            } else {
+                p.fulfilled_by.insert(target.clone(), ());
+            }
+        }
+
+        // Is the required elm in pending?
+        match self.get_pending().get_mut(&target) {
+            Some(t) => {
+                t.add_required_by(is_required_by);
+                // It IS blocking
+                Ok(true)
+            }
+
+            // The required elm must be fulfilled:
+            None => match self.get_fulfilled().get_mut(&target) {
+                // if not err.
+                None => Err(LatticeError::UnknownKey(target)),
+                Some(t) => {
+                    t.add_required_by(is_required_by);
+                    // It is NOT blocking.
+                    Ok(false)
+                }
+            },
+        }
+    }
+
+    fn update_depends_on(
+        &mut self,
+        target: String,
+        depends_on: String,
+    ) -> Result<(), LatticeError> {
+        let (target, depends_on) = (resolved(self, target), resolved(self, depends_on));
+        self.approve(
+            &StructuralChange::AddEdge {
+                requires: &target,
+                is_required: &depends_on,
+            },
+            None,
+        )?;
+        if target == depends_on {
+            return Err(LatticeError::SelfDependency(target));
+        }
+        self.check_known(Some(&depends_on))?;
+        if let Some(cycle) = closing_cycle(self, &target, &depends_on) {
+            return Err(LatticeError::Cycle(cycle));
+        }
+        if self.read_node(&target).is_some() && self.read_node(&depends_on).is_none() {
+            let p = self
+                .get_placeholders()
+                .entry(depends_on.clone())
+                .or_default();
+            p.required_by.insert(target.clone(), ());
+        }
+
+        // Is the required elm in pending?
+        match self.get_pending().get_mut(&target) {
+            Some(t) => {
+                t.add_depends_on(depends_on);
+                Ok(())
+            }
+
+            // The required elm must be fulfilled:
+            None => match self.get_fulfilled().remove(&target) {
+                // Unknown key in either map.
+                None => Err(LatticeError::UnknownKey(target)),
+                Some(mut t) => {
+                    t.add_depends_on(depends_on);
+                    self.append_pending(t);
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    fn add_requirement(
+        &mut self,
+        requires: String,
+        is_required: String,
+    ) -> Result<(), LatticeError> {
+        self.add_requirement_approved(requires, is_required, None)
+    }
+
+    // add_requirement_approved is add_requirement presenting token to the
+    // ChangeGate.
+    fn add_requirement_approved(
+        &mut self,
+        requires: String,
+        is_required: String,
+        token: Option<&ApprovalToken>,
+    ) -> Result<(), LatticeError> {
+        let (requires, is_required) = (resolved(self, requires), resolved(self, is_required));
+        self.approve(
+            &StructuralChange::AddEdge {
+                requires: &requires,
+                is_required: &is_required,
+            },
+            token,
+        )?;
+        if requires == is_required {
+            return Err(LatticeError::SelfDependency(requires));
+        }
+        // both ends must be known before either is touched, in lenient
+        // mode unknown ends become placeholders.
+        self.check_known([&requires, &is_required])?;
+        if let Some(cycle) = closing_cycle(self, &requires, &is_required) {
+            return Err(LatticeError::Cycle(cycle));
+        }
+        if self.read_node(&requires).is_none() || self.read_node(&is_required).is_none() {
+            placeholder_requirement(self, requires, is_required);
+            return Ok(());
+        }
+
+        // search for requires
+        let mut is_still_required = false;
+
+        match self.get_pending().get_mut(&is_required) {
+            Some(is_req) => {
+                is_still_required = true;
+                is_req.add_required_by(requires.clone());
+            }
+            None => match self.get_fulfilled().get_mut(&is_required) {
+                None => return Err(LatticeError::UnknownKey(is_required)),
+                Some(is_req) => {
+                    is_req.add_required_by(requires.clone());
+                }
+            },
+        };
+
+        match self.get_pending().get_mut(&requires) {
+            Some(req) => {
+                req.add_depends_on(is_required.clone());
+                if !is_still_required {
+                    req.depend_fulfilled(is_required).unwrap();
+                }
+            }
+
+            None => {
+                if is_still_required {
+                    let req = match self.get_fulfilled().get_mut(&requires) {
+                        None => return Err(LatticeError::UnknownKey(requires)),
+                        Some(req) => req,
+                    };
+                    req.add_depends_on(is_required);
+                    // a Quorum that is already met doesn't hold requires
+                    // back.
+                    if req.is_blocked() {
+                        let req = self.get_fulfilled().remove(&requires).unwrap();
+                        self.append_pending(req);
+                    }
+                } else {
+                    match self.get_fulfilled().get_mut(&requires) {
+                        None => return Err(LatticeError::UnknownKey(requires)),
+                        Some(req) => {
+                            req.add_depends_on(is_required.clone());
+                            req.depend_fulfilled(is_required).unwrap();
+                        }
+                    }
+                }
+            }
+        };
+
+        Ok(())
+    }
+
+    // add_quorum makes requires wait on need of members rather than all of
+    // them, for quorum style sign offs: an edge is added to each member as
+    // add_requirement would, and once need of them are fulfilled the
+    // others stop holding requires back. Every edge is checked, and
+    // approved, before any is added, and on Err requires is left as it
+    // was. Removing fulfilled members the Quorum can't be met without
+    // drops its unfulfilled members with them. The planners (health,
+    // simulate_execution, schedule_dates) let requires through once need
+    // of the members are done. No members does nothing.
+    fn add_quorum(
+        &mut self,
+        requires: String,
+        members: Vec<String>,
+        need: usize,
+    ) -> Result<(), LatticeError> {
+        let requires = resolved(self, requires);
+        let mut members: Vec<String> = members.into_iter().map(|k| resolved(self, k)).collect();
+        members.sort();
+        members.dedup();
+        if members.is_empty() {
+            return Ok(());
+        }
+        if self.read_node(&requires).is_none() {
+            return Err(LatticeError::UnknownKey(requires));
+        }
+        if members.contains(&requires) {
+            return Err(LatticeError::SelfDependency(requires));
+        }
+        self.check_known(&members)?;
+        for k in &members {
+            if let Some(cycle) = closing_cycle(self, &requires, k) {
+                return Err(LatticeError::Cycle(cycle));
+            }
+            self.approve(
+                &StructuralChange::AddEdge {
+                    requires: &requires,
+                    is_required: k,
+                },
+                None,
+            )?;
+        }
+
+        // what requires held before, to put back should an edge fail.
+        let t = self.read_node(&requires).unwrap();
+        let held: HashMap<String, ()> = t
+            .depends_on()
+            .keys()
+            .chain(t.fulfilled_by().keys())
+            .map(|k| (k.clone(), ()))
+            .collect();
+        let quorums = t.quorums().to_vec();
+        let was_fulfilled = self.read_fulfilled().contains_key(&requires);
+
+        // recorded first and fulfilled members added first, so requires
+        // isn't moved back to pending if the Quorum is met already.
+        let q = Quorum {
+            members: members.clone(),
+            need,
+        };
+        match self.get_pending().get_mut(&requires) {
+            Some(t) => t.get_quorums().push(q),
+            None => self
+                .get_fulfilled()
+                .get_mut(&requires)
+                .unwrap()
+                .get_quorums()
+                .push(q),
+        }
+        members.sort_by_key(|k| !self.read_fulfilled().contains_key(k));
+        for k in &members {
+            if let Err(e) = self.add_requirement(requires.clone(), k.clone()) {
+                for k in members.iter().filter(|k| !held.contains_key(*k)) {
+                    drop_edge(self, &requires, k);
+                }
+                let t = match self.get_pending().remove(&requires) {
+                    Some(t) if was_fulfilled => {
+                        self.get_fulfilled().insert(requires.clone(), t);
+                        self.get_fulfilled().get_mut(&requires).unwrap()
+                    }
+                    Some(t) => {
+                        self.get_pending().insert(requires.clone(), t);
+                        self.get_pending().get_mut(&requires).unwrap()
+                    }
+                    None => self.get_fulfilled().get_mut(&requires).unwrap(),
+                };
+                *t.get_quorums() = quorums;
+                return Err(e);
+            }
+        }
+
+        // the Quorum may spare requires a dependency it was blocked on.
+        let ready = self.read_pending().get(&requires).is_some_and(|t| {
+            !t.is_pending() && t.skipped().is_none() && !self.read_failed().contains_key(&requires)
+        });
+        if ready {
+            self.fulfill(requires)?;
+        }
+        Ok(())
+    }
+
+    // add_any_of is add_quorum needing one of group, as in "craftable from
+    // recipe A or recipe B".
+    fn add_any_of(&mut self, requires: String, group: Vec<String>) -> Result<(), LatticeError> {
+        self.add_quorum(requires, group, 1)
+    }
+
+    // add_soft_requirement records that requires comes after is_required,
+    // for ordering and reporting, without making it wait: add_quorum
+    // needing none. Fulfilling is_required still moves it to requires'
+    // fulfilled_by. An edge requires already holds to is_required is made
+    // soft.
+    fn add_soft_requirement(
+        &mut self,
+        requires: String,
+        is_required: String,
+    ) -> Result<(), LatticeError> {
+        self.add_quorum(requires, vec![is_required], 0)
+    }
+
+    // remove_requirement takes back add_requirement: the edge is dropped
+    // from both ends, whether or not is_required was fulfilled. If that
+    // leaves requires completed with nothing to wait on it is fulfilled,
+    // cascading as usual, unless it has failed or is skipped. Err if
+    // requires holds no such edge.
+    fn remove_requirement(
+        &mut self,
+        requires: &str,
+        is_required: &str,
+    ) -> Result<(), LatticeError> {
+        let (requires, is_required) = (
+            self.canonical(requires).to_string(),
+            self.canonical(is_required).to_string(),
+        );
+        self.approve(
+            &StructuralChange::RemoveEdge {
+                requires: &requires,
+                is_required: &is_required,
+            },
+            None,
+        )?;
+
+        let held = match self.read_node(&requires) {
+            Some(t) => {
+                t.depends_on().contains_key(&is_required)
+                    || t.fulfilled_by().contains_key(&is_required)
+            }
+            None => self.read_placeholders().get(&requires).is_some_and(|p| {
+                p.depends_on.contains_key(&is_required) || p.fulfilled_by.contains_key(&is_required)
+            }),
+        };
+        if !held {
+            return Err(LatticeError::DependencyNotFound {
+                node: requires,
+                dep: is_required,
+            });
+        }
+
+        // whether dropping the edge unblocks requires, checked against the
+        // Limits before anything changes. A failed or skipped LatNode stays
+        // pending, as it would in a cascade.
+        let unblocks = self.read_pending().get(&requires).is_some_and(|t| {
+            t.is_completed()
+                && t.waiting_on() == [&is_required]
+                && t.skipped().is_none()
+                && !self.read_failed().contains_key(&requires)
+        });
+        if unblocks {
+            self.check_limits(&requires)?;
+        }
+
+        let gone: HashMap<String, ()> = HashMap::from([(is_required.clone(), ())]);
+        spend_quorums(self, &requires, &gone);
+        drop_edge(self, &requires, &is_required);
+
+        if unblocks {
+            self.fulfill_from(requires, None, &mut || true)?;
+        }
+        Ok(())
+    }
+
+    // add_reference records that from is related to to without making it
+    // wait on it. Both LatNodes must exist.
+    fn add_reference(&mut self, from: String, to: String) -> Result<(), LatticeError> {
+        let (from, to) = (resolved(self, from), resolved(self, to));
+        if self.read_node(&to).is_none() {
+            return Err(LatticeError::UnknownKey(to));
+        }
+        self.approve(
+            &StructuralChange::AddReference {
+                from: &from,
+                to: &to,
+            },
+            None,
+        )?;
+
+        match self.get_pending().get_mut(&from) {
+            Some(t) => t.add_reference(to),
+            None => match self.get_fulfilled().get_mut(&from) {
+                None => return Err(LatticeError::UnknownKey(from)),
+                Some(t) => t.add_reference(to),
+            },
+        };
+
+        Ok(())
+    }
+
+    // remove_reference drops a reference added by add_reference, Err if
+    // from doesn't exist or holds no such reference.
+    fn remove_reference(&mut self, from: &str, to: &str) -> Result<(), LatticeError> {
+        let (from, to) = (
+            self.canonical(from).to_string(),
+            self.canonical(to).to_string(),
+        );
+        let (from, to) = (from.as_str(), to.as_str());
+        self.approve(&StructuralChange::RemoveReference { from, to }, None)?;
+
+        let t = match self.get_pending().get_mut(from) {
+            Some(t) => t,
+            None => match self.get_fulfilled().get_mut(from) {
+                None => return Err(LatticeError::UnknownKey(from.to_string())),
+                Some(t) => t,
+            },
+        };
+
+        match t.get_references().remove(to) {
+            Some(_) => Ok(()),
+            None => Err(LatticeError::ReferenceNotFound {
+                from: from.to_string(),
+                to: to.to_string(),
+            }),
+        }
+    }
+
+    // retain keeps only the LatNodes keep returns true for, then drops
+    // every edge and reference to the removed ones in a single pass and
+    // retires their handles. Pending LatNodes left with nothing to wait on
+    // become ready but are not fulfilled. Err if approve refuses removing
+    // any of them.
+    fn retain<F>(&mut self, mut keep: F) -> Result<(), LatticeError>
+    where
+        F: FnMut(&str, &T) -> bool,
+    {
+        // decide everything before touching anything, so a refusal leaves
+        // the lattice as it was.
+        let removed: HashMap<String, ()> = self
+            .read_pending()
+            .iter()
+            .chain(self.read_fulfilled().iter())
+            .filter(|(k, t)| !keep(k, t))
+            .map(|(k, _)| (k.clone(), ()))
+            .collect();
+        for k in removed.keys() {
+            self.approve(&StructuralChange::Remove(k), None)?;
+        }
+        self.get_pending().retain(|k, _| !removed.contains_key(k));
+        self.get_fulfilled().retain(|k, _| !removed.contains_key(k));
+
+        forget(self, &removed);
+        Ok(())
+    }
+
+    // remove takes key out of the lattice, scrubbing it from every other
+    // LatNode's relations, and returns what was removed, key first. With
+    // RemoveMode::Cascade everything that transitively requires key goes
+    // too, with Orphan it stays, and pending LatNodes left with nothing to
+    // wait on become ready but are not fulfilled. Err if the lattice
+    // doesn't hold key or approve refuses removing any of them.
+    fn remove(&mut self, key: &str, mode: RemoveMode) -> Result<Vec<(String, T)>, LatticeError> {
+        self.remove_approved(key, mode, None)
+    }
+
+    // remove_approved is remove presenting token to the ChangeGate.
+    fn remove_approved(
+        &mut self,
+        key: &str,
+        mode: RemoveMode,
+        token: Option<&ApprovalToken>,
+    ) -> Result<Vec<(String, T)>, LatticeError> {
+        let key = self.canonical(key).to_string();
+        if self.read_node(&key).is_none() {
+            return Err(LatticeError::UnknownKey(key));
+        }
+
+        // gather everything going first, so a refusal leaves the lattice
+        // as it was.
+        let mut order = Vec::new();
+        let mut removed = HashMap::<String, ()>::new();
+        let mut queue = vec![key];
+        while let Some(k) = queue.pop() {
+            if removed.contains_key(&k) {
+                continue;
+            }
+            let t = match self.read_node(&k) {
+                None => continue,
+                Some(t) => t,
+            };
+
+            if mode == RemoveMode::Cascade {
+                queue.extend(t.required_by().keys().cloned());
+            }
+            removed.insert(k.clone(), ());
+            order.push(k);
+        }
+        for k in &order {
+            self.approve(&StructuralChange::Remove(k), token)?;
+        }
+
+        let mut out = Vec::new();
+        for k in order {
+            let t = match self.get_pending().remove(&k) {
+                Some(t) => t,
+                None => match self.get_fulfilled().remove(&k) {
+                    None => continue,
+                    Some(t) => t,
+                },
+            };
+            out.push((k, t));
+        }
+
+        forget(self, &removed);
+        Ok(out)
+    }
+
+    // take is remove for a single LatNode, orphaning whatever requires it,
+    // handing the LatNode back for its data to be reclaimed, see
+    // BasicNode::into_inner. None if the lattice is frozen, doesn't hold
+    // key or the MutationPolicy refuses.
+    fn take(&mut self, key: &str) -> Option<T> {
+        let mut removed = self.remove(key, RemoveMode::Orphan).ok()?;
+        removed.pop().map(|(_, t)| t)
+    }
+
+    // drain_fulfilled removes and returns every fulfilled LatNode, dropping
+    // the edges pending LatNodes still held to them and their handles.
+    // Err if approve refuses removing any of them.
+    fn drain_fulfilled(&mut self) -> Result<Vec<(String, T)>, LatticeError> {
+        for k in self.read_fulfilled().keys() {
+            self.approve(&StructuralChange::Remove(k), None)?;
+        }
+
+        let drained: Vec<(String, T)> = self.get_fulfilled().drain().collect();
+        let removed: HashMap<String, ()> = drained.iter().map(|(k, _)| (k.clone(), ())).collect();
+        forget(self, &removed);
+        Ok(drained)
+    }
+
+    // leq reports whether other is a forward progression of self: the same
+    // LatNodes and edges, every LatNode fulfilled here is fulfilled there,
+    // and every dependency satisfied here is satisfied there.
+    fn leq<M>(&self, other: &M) -> bool
+    where
+        M: LatMachine<T, U>,
+    {
+        let ours = self.read_pending().len() + self.read_fulfilled().len();
+        let theirs = other.read_pending().len() + other.read_fulfilled().len();
+        if ours != theirs {
+            return false;
+        }
+
+        let nodes = self
+            .read_pending()
+            .iter()
+            .map(|(k, t)| (k, t, false))
+            .chain(self.read_fulfilled().iter().map(|(k, t)| (k, t, true)));
+
+        for (k, t, fulfilled) in nodes {
+            let (o, o_fulfilled) = match other.read_pending().get(k) {
+                Some(o) => (o, false),
+                None => match other.read_fulfilled().get(k) {
+                    None => return false,
+                    Some(o) => (o, true),
+                },
+            };
+
+            if fulfilled && !o_fulfilled {
+                return false;
+            }
+
+            if t.required_by().len() != o.required_by().len()
+                || t.required_by()
+                    .keys()
+                    .any(|r| !o.required_by().contains_key(r))
+            {
+                return false;
+            }
+
+            let deps = t.depends_on().len() + t.fulfilled_by().len();
+            if deps != o.depends_on().len() + o.fulfilled_by().len() {
+                return false;
+            }
+
+            for d in t.depends_on().keys() {
+                if !o.depends_on().contains_key(d) && !o.fulfilled_by().contains_key(d) {
+                    return false;
+                }
+            }
+
+            for d in t.fulfilled_by().keys() {
+                if !o.fulfilled_by().contains_key(d) {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    // sync_from takes a client's copy of this lattice, which may be ahead
+    // in places and stale in others, and applies its legal forward
+    // progress here: every LatNode the client fulfilled that is still
+    // pending here with the same dependencies, and nothing left to wait on
+    // once those before it are in, is fulfilled, in the client's
+    // fulfillment order where known, with OriginKind::Replica.
+    // Returns the corrections the client needs to match this lattice
+    // afterwards.
+    fn sync_from<M>(&mut self, client: &M) -> SyncCorrections
+    where
+        M: LatMachine<T, U>,
+    {
+        let same_deps = |a: &T, b: &T| {
+            a.depends_on().len() + a.fulfilled_by().len()
+                == b.depends_on().len() + b.fulfilled_by().len()
+                && a.depends_on()
+                    .keys()
+                    .chain(a.fulfilled_by().keys())
+                    .all(|d| b.depends_on().contains_key(d) || b.fulfilled_by().contains_key(d))
+        };
+
+        let mut order: Vec<&String> = client
+            .read_fulfillment_log()
+            .iter()
+            .map(|f| &f.key)
+            .filter(|k| client.read_fulfilled().contains_key(*k))
+            .collect();
+        let mut rest: Vec<&String> = client.read_fulfilled().keys().collect();
+        rest.sort();
+        order.extend(rest);
+
+        let mut seen = HashMap::<&String, ()>::new();
+        order.retain(|k| seen.insert(*k, ()).is_none());
+
+        // passes until nothing more goes in, so a LatNode is taken once
+        // whatever it waits on here has been, whatever the client's order.
+        let from = self.read_fulfillment_log().len();
+        let mut progress = true;
+        while progress {
+            progress = false;
+            for k in &order {
+                let legal = match (self.read_pending().get(*k), client.read_fulfilled().get(*k)) {
+                    (Some(ours), Some(theirs)) => !ours.is_blocked() && same_deps(ours, theirs),
+                    _ => false,
+                };
+
+                // a rejected fulfillment, or one still waiting here, shows
+                // up as a revert below.
+                if legal && self.fulfill((*k).clone()).is_ok() {
+                    progress = true;
+                }
+            }
+        }
+
+        // what the client had fulfilled came from the client, traced
+        // back to the run that did the work if it knows it.
+        let n = self.read_fulfillment_log().len();
+        for i in from..n {
+            let key = self.read_fulfillment_log()[i].key.clone();
+            let theirs = match client.read_fulfilled().get(&key) {
+                None => continue,
+                Some(t) => t,
+            };
+            let origin = Origin {
+                run: match theirs.origin() {
+                    Some(o) => o.run.clone(),
+                    None => client.read_run_id().clone(),
+                },
+                kind: OriginKind::Replica,
+            };
+            if let Some(t) = self.get_fulfilled().get_mut(&key) {
+                t.set_origin(Some(origin.clone()));
+            }
+            self.get_fulfillment_log()[i].origin = origin;
+        }
+
+        let mut c = SyncCorrections::default();
+        for k in self.read_fulfilled().keys() {
+            if client.read_pending().contains_key(k) {
+                c.fulfill.push(k.clone());
+            }
+        }
+        for k in self.read_pending().keys() {
+            if client.read_fulfilled().contains_key(k) {
+                c.revert.push(k.clone());
+            }
+        }
+        for k in self
+            .read_pending()
+            .keys()
+            .chain(self.read_fulfilled().keys())
+        {
+            if client.read_node(k).is_none() {
+                c.missing.push(k.clone());
+            }
+        }
+        for k in client
+            .read_pending()
+            .keys()
+            .chain(client.read_fulfilled().keys())
+        {
+            if self.read_node(k).is_none() {
+                c.unknown.push(k.clone());
+            }
+        }
+
+        c.fulfill.sort();
+        c.revert.sort();
+        c.missing.sort();
+        c.unknown.sort();
+        c
+    }
+
+    // returns the summed cost of every fulfilled LatNode.
+    fn spent_cost(&self) -> u64 {
+        self.read_fulfilled().values().map(|t| t.cost()).sum()
+    }
+
+    // returns the summed cost of every pending LatNode.
+    fn estimated_remaining_cost(&self) -> u64 {
+        self.read_pending().values().map(|t| t.cost()).sum()
+    }
+
+    // returns the cost still outstanding before key can be fulfilled:
+    // key itself plus every pending LatNode it transitively depends on.
+    // Shared dependencies are only counted once.
+    fn estimated_remaining_cost_under(&self, key: &str) -> Result<u64, LatticeError> {
+        let key = self.canonical(key);
+        let pending = self.read_pending();
+        if !pending.contains_key(key) {
+            return if self.read_fulfilled().contains_key(key) {
+                Ok(0)
+            } else {
+                Err(LatticeError::UnknownKey(key.to_string()))
+            };
+        }
+
+        let mut seen = HashMap::<String, ()>::new();
+        let mut stack = vec![key.to_string()];
+        let mut total = 0;
+
+        while let Some(k) = stack.pop() {
+            if seen.insert(k.clone(), ()).is_some() {
+                continue;
+            }
+
+            if let Some(t) = pending.get(&k) {
+                total += t.cost();
+                for d in t.depends_on().keys() {
+                    stack.push(d.clone());
+                }
+            }
+        }
+
+        Ok(total)
+    }
+
+    // returns an estimate of the heap held by the lattice, split into node
+    // payloads, relation maps and the pending/fulfilled indexes.
+    fn memory_footprint(&self) -> MemoryFootprint
+    where
+        T: HeapSize,
+    {
+        let mut f = MemoryFootprint::default();
+        for m in [self.read_pending(), self.read_fulfilled()].iter() {
+            f.indexes += m.capacity() * (size_of::<(String, T)>() + 1)
+                + m.keys().map(|k| k.heap_size()).sum::<usize>();
+
+            for t in m.values() {
+                f.nodes += t.heap_size();
+                f.edges += t.depends_on().heap_size()
+                    + t.required_by().heap_size()
+                    + t.fulfilled_by().heap_size()
+                    + t.references().heap_size();
+            }
+        }
+        f.indexes += self.read_handles().heap_size();
+
+        f
+    }
+
+    // compact shrinks the pending/fulfilled tables and every node's
+    // relation maps to fit their contents, typically after large removals.
+    // Returns an estimate of the bytes freed.
+    fn compact(&mut self) -> usize {
+        let mut freed = 0;
+        for t in self.get_pending().values_mut() {
+            freed += shrink_map(t.get_depends_on())
+                + shrink_map(t.get_required_by())
+                + shrink_map(t.get_fulfilled_by())
+                + shrink_map(t.get_references());
+        }
+
+        for t in self.get_fulfilled().values_mut() {
+            freed += shrink_map(t.get_depends_on())
+                + shrink_map(t.get_required_by())
+                + shrink_map(t.get_fulfilled_by())
+                + shrink_map(t.get_references());
+        }
+
+        freed + shrink_map(self.get_pending()) + shrink_map(self.get_fulfilled())
+    }
+
+    // render_tree draws the dependencies under root as an indented Unicode
+    // tree, marking each LatNode ✅ fulfilled, ⏳ pending or 🔒 blocked.
+    // Shared dependencies are expanded once and referenced after that.
+    fn render_tree(&self, root: &str) -> Result<String, LatticeError>
+    where
+        Self: Sized,
+    {
+        render::tree(self, root)
+    }
+
+    // render_layers draws the whole lattice as a layered text diagram,
+    // one line per level with roots at the top. Meant for lattices of up
+    // to a few hundred LatNodes.
+    fn render_layers(&self) -> String
+    where
+        Self: Sized,
+    {
+        render::layers(self)
+    }
+
+    // render_swimlanes is render_layers split into one block per
+    // NodeType::group, levels numbered across the whole lattice so lanes
+    // line up.
+    fn render_swimlanes(&self) -> String
+    where
+        Self: Sized,
+    {
+        render::swimlanes(self)
+    }
+
+    // apply commits changes as one transaction: every staged node, edge
+    // and fulfillment, or nothing along with the Preview saying why.
+    fn apply(&mut self, changes: Changeset<T>) -> Result<(), (Changeset<T>, Preview)>
+    where
+        Self: Sized,
+    {
+        changes.commit(self)
+    }
+
+    // merge copies every LatNode of other in, with the edges and
+    // references between them, re-deriving each one's state here. A key
+    // both lattices hold is dealt with as on_duplicate says: Reject reports
+    // it, Keep leaves ours as it is, Replace swaps in other's LatNode and
+    // its dependencies while keeping our dependents, and MergeEdges keeps
+    // ours and adds other's dependencies to it. Everything is checked as
+    // one Changeset first, with replaced LatNodes' old dependencies still
+    // counted, and on any problem nothing changes. other's aliases, failures
+    // and fulfillment log aren't carried over, nor are references the
+    // ChangeGate turns down.
+    fn merge<L>(&mut self, other: &L, on_duplicate: DuplicatePolicy) -> Result<(), Preview>
+    where
+        Self: Sized,
+        L: LatMachine<T, U>,
+        T: Clone,
+    {
+        let mut theirs: Vec<&T> = other
+            .read_pending()
+            .values()
+            .chain(other.read_fulfilled().values())
+            .collect();
+        theirs.sort_by_key(|t| t.uuid());
+
+        let mut changes = Changeset::new();
+        let mut replaced = Vec::new();
+        let mut problems = Vec::new();
+        // whether each of theirs is left out for ours.
+        let mut kept = Vec::with_capacity(theirs.len());
+        for t in &theirs {
+            let key = t.uuid();
+            // an alias can't be merged into, whatever on_duplicate says.
+            if self.read_aliases().contains_key(&key) {
+                problems.push(ImportProblem::DuplicateKey(key));
+                kept.push(true);
+                continue;
+            }
+            let taken =
+                self.read_pending().contains_key(&key) || self.read_fulfilled().contains_key(&key);
+            kept.push(taken && on_duplicate == DuplicatePolicy::Keep);
+            if !(taken && on_duplicate == DuplicatePolicy::Keep) {
+                let mut deps: Vec<&String> = t
+                    .depends_on()
+                    .keys()
+                    .chain(t.fulfilled_by().keys())
+                    .collect();
+                deps.sort();
+                for d in deps {
+                    changes.stage_edge(key.clone(), d.clone());
+                }
+            }
+
+            // edges and references are added once every LatNode is in.
+            let mut t = (*t).clone();
+            t.get_depends_on().clear();
+            t.get_required_by().clear();
+            t.get_fulfilled_by().clear();
+            t.get_references().clear();
+            match (taken, on_duplicate) {
+                (false, _) => changes.stage(t),
+                (true, DuplicatePolicy::Reject) => problems.push(ImportProblem::DuplicateKey(key)),
+                (true, DuplicatePolicy::Replace) => replaced.push(t),
+                (true, _) => {}
+            }
+        }
+
+        let mut preview = changes.preview(self);
+        if !replaced.is_empty()
+            && self.is_frozen()
+            && !preview.problems.contains(&ImportProblem::Frozen)
+        {
+            preview.problems.push(ImportProblem::Frozen);
+        }
+        for t in &replaced {
+            if let Err(LatticeError::Denied(reason)) =
+                self.permit(&Mutation::Structural(StructuralChange::Replace(&t.uuid())))
+            {
+                preview.problems.push(ImportProblem::Denied(reason));
+            }
+        }
+        preview.problems.extend(problems);
+        if !preview.is_clean() {
+            return Err(preview);
+        }
+
+        // replaced LatNodes lose our dependencies before other's are added,
+        // then are swapped for theirs with whatever relations they end up
+        // with, so nothing cascades off them half wired. What they lose is
+        // kept, (key, depends_on, fulfilled_by), to put back should the
+        // commit still fail, say on a stateful MutationPolicy.
+        let placeholders = self.read_placeholders().clone();
+        let mut stripped = Vec::with_capacity(replaced.len());
+        for t in &replaced {
+            let key = t.uuid();
+            let old = match self.get_pending().get_mut(&key) {
+                Some(old) => old,
+                None => self.get_fulfilled().get_mut(&key).unwrap(),
+            };
+            let waiting: Vec<String> = old.get_depends_on().drain().map(|(d, _)| d).collect();
+            let done: Vec<String> = old.get_fulfilled_by().drain().map(|(d, _)| d).collect();
+            for d in waiting.iter().chain(&done) {
+                if let Some(d) = self.get_pending().get_mut(d) {
+                    d.get_required_by().remove(&key);
+                } else if let Some(d) = self.get_fulfilled().get_mut(d) {
+                    d.get_required_by().remove(&key);
+                } else if let Some(p) = self.get_placeholders().get_mut(d) {
+                    p.required_by.remove(&key);
+                }
+            }
+            stripped.push((key, waiting, done));
+        }
+        self.get_placeholders().retain(|_, p| !p.is_empty());
+        if let Err((_, preview)) = changes.commit(self) {
+            for (key, waiting, done) in stripped {
+                for d in waiting.iter().chain(&done) {
+                    if let Some(d) = self.get_pending().get_mut(d) {
+                        d.add_required_by(key.clone());
+                    } else if let Some(d) = self.get_fulfilled().get_mut(d) {
+                        d.add_required_by(key.clone());
+                    }
+                }
+                let old = match self.get_pending().get_mut(&key) {
+                    Some(old) => old,
+                    None => self.get_fulfilled().get_mut(&key).unwrap(),
+                };
+                for d in waiting {
+                    old.add_depends_on(d);
+                }
+                for d in done {
+                    old.get_fulfilled_by().insert(d, ());
+                }
+            }
+            *self.get_placeholders() = placeholders;
+            return Err(preview);
+        }
+        for mut t in replaced {
+            let key = t.uuid();
+            let old = self.read_node(&key).unwrap();
+            *t.get_depends_on() = old.depends_on().clone();
+            *t.get_required_by() = old.required_by().clone();
+            *t.get_fulfilled_by() = old.fulfilled_by().clone();
+            replace(self, t);
+        }
+
+        for (t, kept) in theirs.into_iter().zip(kept) {
+            if kept {
+                continue;
+            }
+            for r in t.references().keys() {
+                let _ = self.add_reference(t.uuid(), r.clone());
+            }
+        }
+        Ok(())
+    }
+
+    // to_plantuml_wbs exports the work breakdown under root, or under
+    // every LatNode nothing requires when root is None, as a PlantUML WBS
+    // diagram coloured by state.
+    fn to_plantuml_wbs(&self, root: Option<&str>) -> Result<String, LatticeError>
+    where
+        Self: Sized,
+    {
+        render::plantuml_wbs(self, root)
+    }
+
+    // run_to_completion executes the lattice with f, handing it every
+    // ready LatNode and applying the result with update_value, until
+    // nothing is left ready. Stops at the first error, leaving whatever ran
+    // before it applied. Either way returns a RunReport of what ran, in
+    // order.
+    fn run_to_completion<E, F>(&mut self, f: F) -> Result<RunReport, (RunReport, RunError<E>)>
+    where
+        Self: Sized,
+        F: FnMut(&str, &T) -> Result<U, E>,
+    {
+        run::run(self, f)
+    }
+
+    // run_to_completion_parallel is run_to_completion calling f on up to
+    // threads scoped threads at once, one wave of ready LatNodes at a time.
+    fn run_to_completion_parallel<E, F>(
+        &mut self,
+        threads: usize,
+        f: F,
+    ) -> Result<RunReport, (RunReport, RunError<E>)>
+    where
+        Self: Sized,
+        T: Sync,
+        U: Send,
+        E: Send,
+        F: Fn(&str, &T) -> Result<U, E> + Sync,
+    {
+        run::run_parallel(self, threads, f)
+    }
+
+    // run_to_completion_timed is run_to_completion for work that may hang.
+    // prepare turns each ready LatNode into a job run on its own thread,
+    // and a job outliving its LatNode's NodeType::timeout is abandoned and
+    // reported to on_timeout. It is retried up to retries times, then the
+    // LatNode is failed. A job that panics ends the run with
+    // RunError::Panicked.
+    fn run_to_completion_timed<E, P, J>(
+        &mut self,
+        retries: u32,
+        prepare: P,
+        on_timeout: &mut dyn FnMut(&Timeout),
+    ) -> Result<RunReport, (RunReport, RunError<E>)>
+    where
+        Self: Sized,
+        U: Send + 'static,
+        E: Send + 'static,
+        P: FnMut(&str, &T) -> J,
+        J: FnOnce() -> Result<U, E> + Send + 'static,
+    {
+        run::run_timed(self, retries, prepare, on_timeout)
+    }
+
+    // subgraph copies key and every LatNode reachable from it in
+    // direction into a new lattice, keeping only the edges and references
+    // within that set. Each LatNode keeps its state and any failure, so
+    // with Dependents a LatNode can be left pending on nothing.
+    fn subgraph(&self, key: &str, direction: Direction) -> Result<Self, LatticeError>
+    where
+        Self: Sized,
+        T: Clone,
+    {
+        let root = self.canonical(key);
+        if self.read_node(root).is_none() {
+            return Err(LatticeError::UnknownKey(root.to_string()));
+        }
+
+        let mut keep = HashMap::<&str, ()>::new();
+        keep.insert(root, ());
+        let mut stack = vec![root];
+        while let Some(k) = stack.pop() {
+            let t = match self.read_node(k) {
+                None => continue,
+                Some(t) => t,
+            };
+            let next: Vec<&String> = match direction {
+                Direction::Dependencies => t
+                    .depends_on()
+                    .keys()
+                    .chain(t.fulfilled_by().keys())
+                    .collect(),
+                Direction::Dependents => t.required_by().keys().collect(),
+            };
+            for n in next {
+                if self.read_node(n).is_some() && keep.insert(n, ()).is_none() {
+                    stack.push(n);
+                }
+            }
+        }
+
+        let mut keys: Vec<&str> = keep.keys().copied().collect();
+        keys.sort();
+        let mut out = Self::new();
+        for k in keys {
+            let mut t = self.read_node(k).unwrap().clone();
+            t.get_depends_on()
+                .retain(|d, _| keep.contains_key(d.as_str()));
+            t.get_required_by()
+                .retain(|r, _| keep.contains_key(r.as_str()));
+            t.get_fulfilled_by()
+                .retain(|d, _| keep.contains_key(d.as_str()));
+            t.get_references()
+                .retain(|r, _| keep.contains_key(r.as_str()));
+            prune_quorums(&mut t);
+            if let Some(reason) = self.read_failed().get(k) {
+                out.get_failed().insert(k.to_string(), reason.clone());
+            }
+            match self.read_fulfilled().contains_key(k) {
+                true => out.append_fulfilled(t),
+                false => out.append_pending(t),
+            };
+        }
+        Ok(out)
+    }
+
+    // diff reports what changed going from this lattice to other: LatNodes
+    // and edges added and removed, and the LatNodes both hold whose
+    // NodeState moved, for reviewing one version of a plan against the
+    // next.
+    fn diff<M>(&self, other: &M) -> LatticeDiff
+    where
+        Self: Sized,
+        M: LatMachine<T, U>,
+    {
+        diff::diff(self, other)
+    }
+
+    // simulate_execution plays out workers executing the pending LatNodes,
+    // each taking durations(key, node) time units, and reports the
+    // makespan and how busy each worker was. For capacity planning: run it
+    // with a few pool sizes and compare.
+    fn simulate_execution<F>(&self, workers: usize, durations: F) -> Simulation
+    where
+        Self: Sized,
+        F: FnMut(&str, &T) -> u64,
+    {
+        simulate::run(self, workers, durations)
+    }
+
+    // duration_sensitivity ranks the pending LatNodes by how much making
+    // each delta longer pushes out simulate_execution's makespan, showing
+    // which estimates are worth refining.
+    fn duration_sensitivity<F>(&self, workers: usize, durations: F, delta: u64) -> Vec<Sensitivity>
+    where
+        Self: Sized,
+        F: FnMut(&str, &T) -> u64,
+    {
+        simulate::sensitivity(self, workers, durations, delta)
+    }
+
+    // schedule_dates turns the pending LatNodes' durations, in working
+    // days on their owner's calendar, into earliest and latest start and
+    // finish dates, starting from start. Latest dates are worked back from
+    // finish_by, or from the earliest the lattice can finish when None.
+    fn schedule_dates<F>(
+        &self,
+        start: Date,
+        calendar: &Calendar,
+        finish_by: Option<Date>,
+        tasks: F,
+    ) -> Schedule
+    where
+        Self: Sized,
+        F: FnMut(&str, &T) -> Task,
+    {
+        calendar::schedule(self, start, calendar, finish_by, tasks)
+    }
+}
+
+pub struct BasicLattice<T> {
+    pending: HashMap<String, T>,
+    fulfilled: HashMap<String, T>,
+    fulfillment_log: Vec<Fulfillment>,
+    fulfill_policy: FulfillPolicy<T>,
+    limits: Limits,
+    handles: HandleTable,
+    change_gate: ChangeGate,
+    placeholders: HashMap<String, Placeholder>,
+    aliases: HashMap<String, String>,
+    forced: HashMap<String, Vec<String>>,
+    failed: HashMap<String, String>,
+    cache: Option<Box<dyn FulfillmentCache>>,
+    run_id: Option<String>,
+    policy: Option<Box<dyn MutationPolicy>>,
+    actor: Option<String>,
+}
+
+impl<T: NodeType> BasicLattice<BasicNode<T>>
+where
+    T: NodeType,
+{
+    // from_node_list builds a lattice from v. Each edge only needs
+    // declaring at one end, as a depends_on or a required_by, the other
+    // end is filled in. Edges to keys v doesn't hold are kept, see
+    // unresolved, or use from_node_list_checked to have them reported.
+    // Err with KeyTaken if two nodes share a key, or SelfDependency if a
+    // node lists itself. There's no FromIterator, collecting can't fail:
+    // collect the nodes into a Vec for this, or try_extend a new lattice.
+    pub fn from_node_list(
+        v: Vec<BasicNode<T>>,
+    ) -> Result<BasicLattice<BasicNode<T>>, LatticeError> {
+        check_node_list(&v)?;
+        let mut v = v;
+        backfill(&mut v);
+        let mut s = BasicLattice::new();
+        let mut keys = Vec::<String>::new();
+        for mut node in v {
+            if !node.is_pending() {
+                keys.push(node.uuid());
+            }
+
+            canonicalize_relations(&s.aliases, &mut node);
+            s.append_pending(node);
+        }
+
+        for key in keys {
+            let _ = s.fulfill(key);
+        }
+        Ok(s)
+    }
+
+    // try_extend appends every node of iter in turn, as append would,
+    // Limits::on_duplicate deciding any key already held. A new node's
+    // edges are added once every node is in, as add_requirement would, so
+    // each only needs declaring at one end. Completed nodes still pending
+    // after that are fulfilled. Err on the first node or edge refused, or a
+    // node listing itself, what came before staying in; stage the nodes in
+    // a Changeset for all or nothing.
+    pub fn try_extend<I>(&mut self, iter: I) -> Result<(), LatticeError>
+    where
+        I: IntoIterator<Item = BasicNode<T>>,
+    {
+        let mut keys = Vec::<String>::new();
+        let mut edges = Vec::<(String, String)>::new();
+        for mut node in iter {
+            self_edge(&node)?;
+            let key = node.uuid();
+            if node.is_completed() {
+                keys.push(key.clone());
+            }
+            if self.read_node(&key).is_none() {
+                let deps = node.get_depends_on().drain();
+                edges.extend(deps.map(|(d, _)| (key.clone(), d)));
+                let reqs = node.get_required_by().drain();
+                edges.extend(reqs.map(|(r, _)| (r, key.clone())));
+            }
+            self.append(node)?;
+        }
+
+        edges.sort();
+        edges.dedup();
+        for (requires, is_required) in edges {
+            self.add_requirement(requires, is_required)?;
+        }
+
+        for key in keys {
+            let ready = self
+                .pending
+                .get(&key)
+                .is_some_and(|t| t.is_completed() && !t.is_blocked() && t.skipped().is_none());
+            if ready && !self.failed.contains_key(&key) {
+                self.fulfill(key)?;
+            }
+        }
+        Ok(())
+    }
+
+    // from_node_list_with is from_node_list for big inputs: progress is
+    // called with (steps done, 2 * v.len()) as nodes are appended and then
+    // fulfilled, and construction is abandoned with Err once cancel is
+    // cancelled. Duplicate keys and self edges fail as in from_node_list.
+    pub fn from_node_list_with(
+        v: Vec<BasicNode<T>>,
+        progress: &mut dyn FnMut(usize, usize),
+        cancel: &CancelToken,
+    ) -> Result<BasicLattice<BasicNode<T>>, LatticeError> {
+        check_node_list(&v)?;
+        let mut v = v;
+        backfill(&mut v);
+        let mut s = BasicLattice::new();
+        let mut keys = Vec::<String>::new();
+        let total = 2 * v.len();
+        let mut done = 0;
+
+        for node in v {
+            if cancel.is_cancelled() {
+                return Err(LatticeError::Cancelled);
+            }
+
+            if !node.is_pending() {
+                keys.push(node.uuid());
+            }
+
+            s.append_pending(node);
+            done += 1;
+            progress(done, total);
+        }
+
+        for key in keys {
+            // already reached by an earlier cascade, or waiting on a node
+            // that will reach it.
+            if s.pending.get(&key).is_none_or(|t| t.is_blocked()) {
+                continue;
+            }
+
+            let mut step = || {
+                done += 1;
+                progress(done, total);
+                !cancel.is_cancelled()
+            };
+            if s.fulfill_from(key, None, &mut step).is_err() && cancel.is_cancelled() {
+                return Err(LatticeError::Cancelled);
+            }
+        }
+
+        // not every node ends up fulfilled, finish the count.
+        if done < total {
+            progress(total, total);
+        }
+        Ok(s)
     }
 }
 
@@ -418,6 +4396,19 @@ where
         BasicLattice {
             pending: HashMap::new(),
             fulfilled: HashMap::new(),
+            fulfillment_log: Vec::new(),
+            fulfill_policy: FulfillPolicy::default(),
+            limits: Limits::default(),
+            handles: HandleTable::new(),
+            change_gate: ChangeGate::default(),
+            placeholders: HashMap::new(),
+            aliases: HashMap::new(),
+            forced: HashMap::new(),
+            failed: HashMap::new(),
+            cache: None,
+            run_id: None,
+            policy: None,
+            actor: None,
         }
     }
 
@@ -436,4 +4427,143 @@ where
     fn get_fulfilled(&mut self) -> &mut HashMap<String, T> {
         &mut self.fulfilled
     }
+
+    fn read_fulfillment_log(&self) -> &Vec<Fulfillment> {
+        &self.fulfillment_log
+    }
+
+    fn get_fulfillment_log(&mut self) -> &mut Vec<Fulfillment> {
+        &mut self.fulfillment_log
+    }
+
+    fn read_fulfill_policy(&self) -> &FulfillPolicy<T> {
+        &self.fulfill_policy
+    }
+
+    fn get_fulfill_policy(&mut self) -> &mut FulfillPolicy<T> {
+        &mut self.fulfill_policy
+    }
+
+    fn read_limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    fn get_limits(&mut self) -> &mut Limits {
+        &mut self.limits
+    }
+
+    fn read_handles(&self) -> &HandleTable {
+        &self.handles
+    }
+
+    fn get_handles(&mut self) -> &mut HandleTable {
+        &mut self.handles
+    }
+
+    fn read_placeholders(&self) -> &HashMap<String, Placeholder> {
+        &self.placeholders
+    }
+
+    fn read_forced(&self) -> &HashMap<String, Vec<String>> {
+        &self.forced
+    }
+
+    fn get_forced(&mut self) -> &mut HashMap<String, Vec<String>> {
+        &mut self.forced
+    }
+
+    fn read_failed(&self) -> &HashMap<String, String> {
+        &self.failed
+    }
+
+    fn get_failed(&mut self) -> &mut HashMap<String, String> {
+        &mut self.failed
+    }
+
+    fn read_run_id(&self) -> &Option<String> {
+        &self.run_id
+    }
+
+    fn get_run_id(&mut self) -> &mut Option<String> {
+        &mut self.run_id
+    }
+
+    fn read_policy(&self) -> &Option<Box<dyn MutationPolicy>> {
+        &self.policy
+    }
+
+    fn get_policy(&mut self) -> &mut Option<Box<dyn MutationPolicy>> {
+        &mut self.policy
+    }
+
+    fn read_actor(&self) -> &Option<String> {
+        &self.actor
+    }
+
+    fn get_actor(&mut self) -> &mut Option<String> {
+        &mut self.actor
+    }
+
+    fn read_cache(&self) -> &Option<Box<dyn FulfillmentCache>> {
+        &self.cache
+    }
+
+    fn get_cache(&mut self) -> &mut Option<Box<dyn FulfillmentCache>> {
+        &mut self.cache
+    }
+
+    fn read_aliases(&self) -> &HashMap<String, String> {
+        &self.aliases
+    }
+
+    fn get_aliases(&mut self) -> &mut HashMap<String, String> {
+        &mut self.aliases
+    }
+
+    fn get_placeholders(&mut self) -> &mut HashMap<String, Placeholder> {
+        &mut self.placeholders
+    }
+
+    fn read_change_gate(&self) -> &ChangeGate {
+        &self.change_gate
+    }
+
+    fn get_change_gate(&mut self) -> &mut ChangeGate {
+        &mut self.change_gate
+    }
+}
+
+// Iterating yields (key, node) pairs, pending LatNodes first.
+impl<T> IntoIterator for BasicLattice<T> {
+    type Item = (String, T);
+    type IntoIter = Chain<hash_map::IntoIter<String, T>, hash_map::IntoIter<String, T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.pending.into_iter().chain(self.fulfilled)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a BasicLattice<T> {
+    type Item = (&'a String, &'a T);
+    type IntoIter = Chain<hash_map::Iter<'a, String, T>, hash_map::Iter<'a, String, T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.pending.iter().chain(self.fulfilled.iter())
+    }
+}
+
+// Indexing looks key up in pending, then fulfilled, and panics if the
+// lattice doesn't hold it. Use LatMachine::read_node to check first.
+impl<T> Index<&str> for BasicLattice<T> {
+    type Output = T;
+
+    fn index(&self, key: &str) -> &T {
+        match self.pending.get(key) {
+            Some(t) => t,
+            None => self
+                .fulfilled
+                .get(key)
+                .unwrap_or_else(|| panic!("no LatNode with key {:?}", key)),
+        }
+    }
 }
@@ -0,0 +1,48 @@
+use crate::approval::StructuralChange;
+
+// Mutation describes a change about to be made to a lattice, as put to a
+// MutationPolicy. Keys are canonical.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mutation<'a> {
+    // a change to the lattice's shape, put to its ChangeGate as well.
+    Structural(StructuralChange<'a>),
+    AddAlias { alias: &'a str, key: &'a str },
+    RemoveAlias(&'a str),
+    // the LatNode fulfilled, along with whatever it cascades to.
+    Fulfill(&'a str),
+    // the LatNode fulfilled by an operator override, whatever it still
+    // waits on, see LatMachine::force_fulfill. Put as Fulfill as well.
+    ForceFulfill(&'a str),
+    // the LatNode moved back to pending, along with its fulfilled
+    // dependents.
+    Reset(&'a str),
+    // the LatNode's data replaced, see LatMachine::update_value.
+    Update(&'a str),
+    Skip(&'a str),
+    Unskip(&'a str),
+    Fail(&'a str),
+    Recover(&'a str),
+    AttachArtifact(&'a str),
+    // the lattice's shape locked or unlocked, see LatMachine::freeze.
+    Freeze,
+    Thaw,
+}
+
+// MutationPolicy is consulted before every change to a lattice that has
+// one, see LatMachine::get_policy, along with who is making it, see
+// LatMachine::get_actor. It is where an external policy engine plugs in,
+// deciding per tenant. Err carries the reason for refusing, and the change
+// fails with LatticeError::Denied, leaving the lattice as it was.
+pub trait MutationPolicy {
+    fn check(&self, actor: Option<&str>, mutation: &Mutation) -> Result<(), String>;
+}
+
+// Any function of the same shape is a MutationPolicy.
+impl<F> MutationPolicy for F
+where
+    F: Fn(Option<&str>, &Mutation) -> Result<(), String>,
+{
+    fn check(&self, actor: Option<&str>, mutation: &Mutation) -> Result<(), String> {
+        self(actor, mutation)
+    }
+}
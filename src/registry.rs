@@ -0,0 +1,244 @@
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+
+use crate::changeset::Preview;
+use crate::template::Template;
+use crate::{LatMachine, WriteNode};
+
+// SpawnError is why LatticeRegistry::spawn registered nothing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpawnError {
+    NoSuchTemplate,
+    NameTaken,
+    // the template didn't instantiate cleanly.
+    Problems(Preview),
+}
+
+// UnknownLattice is returned for a name the registry holds no lattice
+// under, live or archived as the call requires.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownLattice(pub String);
+
+impl SpawnError {
+    // code names the variant, stable as LatticeError::code is.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SpawnError::NoSuchTemplate => "no_such_template",
+            SpawnError::NameTaken => "name_taken",
+            SpawnError::Problems(_) => "template_problems",
+        }
+    }
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpawnError::NoSuchTemplate => write!(f, "no such template"),
+            SpawnError::NameTaken => write!(f, "lattice name is already taken"),
+            SpawnError::Problems(p) => write!(
+                f,
+                "template did not instantiate cleanly: {} problems",
+                p.problems.len()
+            ),
+        }
+    }
+}
+
+impl Error for SpawnError {}
+
+impl UnknownLattice {
+    pub fn code(&self) -> &'static str {
+        "unknown_lattice"
+    }
+}
+
+impl fmt::Display for UnknownLattice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown lattice {}", self.0)
+    }
+}
+
+impl Error for UnknownLattice {}
+
+// LatticeRegistry manages many named lattices. Archived lattices are kept
+// read only and still hold their name until deleted. Templates are stored
+// alongside, by name and version, ready to spawn lattices from.
+pub struct LatticeRegistry<L> {
+    live: HashMap<String, L>,
+    archived: HashMap<String, L>,
+    templates: HashMap<String, BTreeMap<u32, Template>>,
+}
+
+impl<L> Default for LatticeRegistry<L> {
+    fn default() -> Self {
+        LatticeRegistry {
+            live: HashMap::new(),
+            archived: HashMap::new(),
+            templates: HashMap::new(),
+        }
+    }
+}
+
+impl<L> LatticeRegistry<L> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // create registers l under name, handing it back if the name is taken
+    // by a live or archived lattice.
+    pub fn create(&mut self, name: String, l: L) -> Result<&mut L, L> {
+        if self.archived.contains_key(&name) {
+            return Err(l);
+        }
+
+        match self.live.entry(name) {
+            Entry::Occupied(_) => Err(l),
+            Entry::Vacant(e) => Ok(e.insert(l)),
+        }
+    }
+
+    // get returns the live lattice called name.
+    pub fn get(&self, name: &str) -> Option<&L> {
+        self.live.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut L> {
+        self.live.get_mut(name)
+    }
+
+    // get_archived returns the archived lattice called name.
+    pub fn get_archived(&self, name: &str) -> Option<&L> {
+        self.archived.get(name)
+    }
+
+    // archive moves a live lattice out of reach of get_mut.
+    pub fn archive(&mut self, name: &str) -> Result<(), UnknownLattice> {
+        let (name, l) = self
+            .live
+            .remove_entry(name)
+            .ok_or_else(|| UnknownLattice(name.to_string()))?;
+        self.archived.insert(name, l);
+        Ok(())
+    }
+
+    // restore makes an archived lattice live again.
+    pub fn restore(&mut self, name: &str) -> Result<(), UnknownLattice> {
+        let (name, l) = self
+            .archived
+            .remove_entry(name)
+            .ok_or_else(|| UnknownLattice(name.to_string()))?;
+        self.live.insert(name, l);
+        Ok(())
+    }
+
+    // delete removes name, live or archived, returning its lattice.
+    pub fn delete(&mut self, name: &str) -> Option<L> {
+        self.live
+            .remove(name)
+            .or_else(|| self.archived.remove(name))
+    }
+
+    // contains reports whether name is taken, live or archived.
+    pub fn contains(&self, name: &str) -> bool {
+        self.live.contains_key(name) || self.archived.contains_key(name)
+    }
+
+    // names returns the live lattices' names, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut v: Vec<&str> = self.live.keys().map(|k| k.as_str()).collect();
+        v.sort_unstable();
+        v
+    }
+
+    // archived_names returns the archived lattices' names, sorted.
+    pub fn archived_names(&self) -> Vec<&str> {
+        let mut v: Vec<&str> = self.archived.keys().map(|k| k.as_str()).collect();
+        v.sort_unstable();
+        v
+    }
+
+    // iter visits every live lattice, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &L)> {
+        self.live.iter().map(|(k, l)| (k.as_str(), l))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&str, &mut L)> {
+        self.live.iter_mut().map(|(k, l)| (k.as_str(), l))
+    }
+
+    // len counts the live lattices.
+    pub fn len(&self) -> usize {
+        self.live.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live.is_empty()
+    }
+
+    // add_template stores t, handing it back if its name and version are
+    // already taken.
+    pub fn add_template(&mut self, t: Template) -> Result<(), Template> {
+        let versions = self.templates.entry(t.name().to_string()).or_default();
+        if versions.contains_key(&t.version()) {
+            return Err(t);
+        }
+        versions.insert(t.version(), t);
+        Ok(())
+    }
+
+    // template returns name at version, or its latest version for None.
+    pub fn template(&self, name: &str, version: Option<u32>) -> Option<&Template> {
+        let versions = self.templates.get(name)?;
+        match version {
+            Some(v) => versions.get(&v),
+            None => versions.values().next_back(),
+        }
+    }
+
+    // template_versions lists the stored versions of name, ascending.
+    pub fn template_versions(&self, name: &str) -> Vec<u32> {
+        self.templates
+            .get(name)
+            .map(|versions| versions.keys().copied().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn remove_template(&mut self, name: &str, version: u32) -> Option<Template> {
+        let versions = self.templates.get_mut(name)?;
+        let t = versions.remove(&version);
+        if versions.is_empty() {
+            self.templates.remove(name);
+        }
+        t
+    }
+
+    // spawn creates a lattice called name from a stored template, see
+    // Template::instantiate.
+    pub fn spawn<T, U, F>(
+        &mut self,
+        template: &str,
+        version: Option<u32>,
+        name: String,
+        prefix: &str,
+        build: F,
+    ) -> Result<&mut L, SpawnError>
+    where
+        L: LatMachine<T, U>,
+        T: WriteNode<U>,
+        F: FnMut(&str) -> U,
+    {
+        let t = self
+            .template(template, version)
+            .ok_or(SpawnError::NoSuchTemplate)?;
+        if self.contains(&name) {
+            return Err(SpawnError::NameTaken);
+        }
+
+        let mut l = L::new();
+        t.instantiate(&mut l, prefix, build)
+            .map_err(SpawnError::Problems)?;
+        Ok(self.live.entry(name).or_insert(l))
+    }
+}
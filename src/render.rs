@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+
+use crate::error::LatticeError;
+use crate::{LatMachine, WriteNode};
+
+// glyph marks a node as fulfilled, pending with nothing outstanding, or
+// blocked on dependencies.
+fn glyph<T, U>(fulfilled: bool, t: &T) -> &'static str
+where
+    T: WriteNode<U>,
+{
+    if fulfilled {
+        "✅"
+    } else if !t.is_blocked() {
+        "⏳"
+    } else {
+        "🔒"
+    }
+}
+
+// dependencies returns every key t depends on, fulfilled or not, sorted.
+fn dependencies<T, U>(t: &T) -> Vec<&String>
+where
+    T: WriteNode<U>,
+{
+    let mut v: Vec<&String> = t
+        .depends_on()
+        .keys()
+        .chain(t.fulfilled_by().keys())
+        .collect();
+    v.sort();
+    v
+}
+
+// references returns every key t references, sorted.
+fn references<T, U>(t: &T) -> Vec<&String>
+where
+    T: WriteNode<U>,
+{
+    let mut v: Vec<&String> = t.references().keys().collect();
+    v.sort();
+    v
+}
+
+// lookup finds key in either map, reporting whether it is fulfilled.
+fn lookup<'a, L, T, U>(l: &'a L, key: &str) -> Option<(&'a T, bool)>
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+{
+    match l.read_pending().get(key) {
+        Some(t) => Some((t, false)),
+        None => l.read_fulfilled().get(key).map(|t| (t, true)),
+    }
+}
+
+// (key, prefix of its line, whether it is its parent's last child,
+// whether it is a reference rather than a dependency)
+type TreeEntry<'a> = (&'a str, String, bool, bool);
+
+// push_children queues t's dependencies, then its references, so they pop
+// in sorted order with the dependencies first.
+fn push_children<'a, T, U>(stack: &mut Vec<TreeEntry<'a>>, t: &'a T, prefix: String)
+where
+    T: WriteNode<U>,
+{
+    let mut children: Vec<(&String, bool)> =
+        dependencies(t).into_iter().map(|d| (d, false)).collect();
+    children.extend(references(t).into_iter().map(|r| (r, true)));
+    let n = children.len();
+    for (i, (k, is_ref)) in children.into_iter().enumerate().rev() {
+        stack.push((k.as_str(), prefix.clone(), i + 1 == n, is_ref));
+    }
+}
+
+// tree renders the dependencies under root as an indented Unicode tree.
+// A node reached a second time is printed as a reference instead of
+// being expanded again, keys the lattice doesn't hold are marked missing.
+// References are listed after the dependencies, marked ⇢ and not expanded.
+pub fn tree<L, T, U>(l: &L, root: &str) -> Result<String, LatticeError>
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+{
+    let (t, fulfilled) = match lookup(l, root) {
+        None => return Err(LatticeError::UnknownKey(root.to_string())),
+        Some(found) => found,
+    };
+
+    let mut out = format!("{} {}\n", glyph(fulfilled, t), root);
+    let mut seen = HashMap::<&str, ()>::new();
+    seen.insert(root, ());
+
+    let mut stack: Vec<TreeEntry> = Vec::new();
+    push_children(&mut stack, t, String::new());
+
+    while let Some((key, prefix, last, is_ref)) = stack.pop() {
+        let connector = if last { "└── " } else { "├── " };
+        match lookup(l, key) {
+            None => {
+                let arrow = if is_ref { "⇢ " } else { "" };
+                out.push_str(&format!(
+                    "{}{}{}❓ {} (missing)\n",
+                    prefix, connector, arrow, key
+                ));
+            }
+            Some((t, fulfilled)) if is_ref => {
+                out.push_str(&format!(
+                    "{}{}⇢ {} {}\n",
+                    prefix,
+                    connector,
+                    glyph(fulfilled, t),
+                    key
+                ));
+            }
+            Some((t, fulfilled)) => {
+                if seen.insert(key, ()).is_some() {
+                    out.push_str(&format!(
+                        "{}{}↺ {} {} (see above)\n",
+                        prefix,
+                        connector,
+                        glyph(fulfilled, t),
+                        key
+                    ));
+                } else {
+                    out.push_str(&format!(
+                        "{}{}{} {}\n",
+                        prefix,
+                        connector,
+                        glyph(fulfilled, t),
+                        key
+                    ));
+                    let indent = if last { "    " } else { "│   " };
+                    push_children(&mut stack, t, prefix + indent);
+                }
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+// levels groups every key by its depth: roots (nothing to depend on) at
+// level 0, everything else one below its deepest dependency. Keys caught
+// in a cycle can't be levelled and are returned separately. Dependencies
+// on keys the lattice doesn't hold are ignored.
+pub fn levels<L, T, U>(l: &L) -> (Vec<Vec<String>>, Vec<String>)
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+{
+    let mut waiting = HashMap::<&str, usize>::new();
+    let mut dependents = HashMap::<&str, Vec<&str>>::new();
+    let mut current = Vec::<&str>::new();
+
+    for (k, t) in l.read_pending().iter().chain(l.read_fulfilled().iter()) {
+        let deps: Vec<&String> = dependencies(t)
+            .into_iter()
+            .filter(|d| lookup(l, d).is_some())
+            .collect();
+        for d in &deps {
+            dependents.entry(d.as_str()).or_default().push(k.as_str());
+        }
+        if deps.is_empty() {
+            current.push(k.as_str());
+        } else {
+            waiting.insert(k.as_str(), deps.len());
+        }
+    }
+
+    let mut out = Vec::new();
+    while !current.is_empty() {
+        let mut next = Vec::new();
+        for k in &current {
+            for d in dependents.get(k).into_iter().flatten() {
+                if let Some(n) = waiting.get_mut(d) {
+                    *n -= 1;
+                    if *n == 0 {
+                        waiting.remove(d);
+                        next.push(*d);
+                    }
+                }
+            }
+        }
+
+        let mut level: Vec<String> = current.iter().map(|k| k.to_string()).collect();
+        level.sort();
+        out.push(level);
+        current = next;
+    }
+
+    let mut cyclic: Vec<String> = waiting.keys().map(|k| k.to_string()).collect();
+    cyclic.sort();
+    (out, cyclic)
+}
+
+// layers renders the whole lattice as a layered (Hasse style) text
+// diagram, one line per level with roots at the top and each node
+// followed by the keys it depends on, then any it references.
+pub fn layers<L, T, U>(l: &L) -> String
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+{
+    let (levels, cyclic) = levels(l);
+    draw_layers(l, &levels, &cyclic)
+}
+
+// swimlanes renders layers once per group, ungrouped nodes last. Each
+// lane keeps the whole lattice's level numbers and skips levels it has no
+// nodes on.
+pub fn swimlanes<L, T, U>(l: &L) -> String
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+{
+    let (levels, cyclic) = levels(l);
+    let group = |k: &String| lookup(l, k).and_then(|(t, _)| t.group());
+
+    let mut lanes: Vec<Option<String>> = levels
+        .iter()
+        .flatten()
+        .chain(cyclic.iter())
+        .map(group)
+        .collect();
+    // None sorts first, ungrouped goes last.
+    lanes.sort();
+    lanes.dedup();
+    if lanes.first() == Some(&None) {
+        lanes.rotate_left(1);
+    }
+
+    let mut out = String::new();
+    for lane in lanes {
+        let pick = |keys: &Vec<String>| -> Vec<String> {
+            keys.iter().filter(|k| group(k) == lane).cloned().collect()
+        };
+        let lane_levels: Vec<Vec<String>> = levels.iter().map(pick).collect();
+        out.push_str(&format!(
+            "── {} ──\n",
+            lane.as_deref().unwrap_or("ungrouped")
+        ));
+        out.push_str(&draw_layers(l, &lane_levels, &pick(&cyclic)));
+    }
+    out
+}
+
+// draw_layers renders levels, as from levels, skipping empty ones.
+fn draw_layers<L, T, U>(l: &L, levels: &[Vec<String>], cyclic: &[String]) -> String
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+{
+    let entry = |k: &str| match lookup(l, k) {
+        None => format!("❓ {}", k),
+        Some((t, fulfilled)) => {
+            let mut s = format!("{} {}", glyph(fulfilled, t), k);
+            let deps: Vec<&str> = dependencies(t).iter().map(|d| d.as_str()).collect();
+            if !deps.is_empty() {
+                s.push_str(&format!(" ← {}", deps.join(", ")));
+            }
+            let refs: Vec<&str> = references(t).iter().map(|r| r.as_str()).collect();
+            if !refs.is_empty() {
+                s.push_str(&format!(" ⇢ {}", refs.join(", ")));
+            }
+            s
+        }
+    };
+
+    let width = levels.len().to_string().len();
+    let mut out = String::new();
+    for (i, level) in levels.iter().enumerate() {
+        if level.is_empty() {
+            continue;
+        }
+        let entries: Vec<String> = level.iter().map(|k| entry(k)).collect();
+        out.push_str(&format!(
+            "L{:<w$} │ {}\n",
+            i,
+            entries.join("   "),
+            w = width
+        ));
+    }
+
+    if !cyclic.is_empty() {
+        let entries: Vec<String> = cyclic.iter().map(|k| entry(k)).collect();
+        out.push_str(&format!("cycle │ {}\n", entries.join("   ")));
+    }
+
+    out
+}
+
+// wbs_color maps a node's state onto a PlantUML WBS background.
+fn wbs_color<T, U>(fulfilled: bool, t: &T) -> &'static str
+where
+    T: WriteNode<U>,
+{
+    if fulfilled {
+        "#palegreen"
+    } else if !t.is_blocked() {
+        "#khaki"
+    } else {
+        "#lightpink"
+    }
+}
+
+// plantuml_wbs renders the work breakdown under root as PlantUML WBS,
+// coloured by state. Without a root every LatNode nothing requires becomes
+// a top level branch under a synthetic "lattice" node. Shared dependencies
+// are expanded once and referenced after that.
+pub fn plantuml_wbs<L, T, U>(l: &L, root: Option<&str>) -> Result<String, LatticeError>
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+{
+    let mut stack: Vec<(&str, usize)> = Vec::new();
+    let mut out = String::from("@startwbs\n");
+    match root {
+        Some(root) => {
+            if lookup(l, root).is_none() {
+                return Err(LatticeError::UnknownKey(root.to_string()));
+            }
+            stack.push((root, 1));
+        }
+        None => {
+            out.push_str("* lattice\n");
+            let mut sinks: Vec<&String> = l
+                .read_pending()
+                .iter()
+                .chain(l.read_fulfilled().iter())
+                .filter(|(_, t)| t.required_by().is_empty())
+                .map(|(k, _)| k)
+                .collect();
+            sinks.sort();
+            for k in sinks.into_iter().rev() {
+                stack.push((k.as_str(), 2));
+            }
+        }
+    }
+
+    let mut seen = HashMap::<&str, ()>::new();
+    while let Some((key, depth)) = stack.pop() {
+        let stars = "*".repeat(depth);
+        let label = key.replace(&['\r', '\n'][..], " ");
+        match lookup(l, key) {
+            None => out.push_str(&format!("{}[#lightgray] {} (missing)\n", stars, label)),
+            Some((t, fulfilled)) => {
+                let color = wbs_color(fulfilled, t);
+                if seen.insert(key, ()).is_some() {
+                    out.push_str(&format!("{}[{}] {} (see above)\n", stars, color, label));
+                } else {
+                    out.push_str(&format!("{}[{}] {}\n", stars, color, label));
+                    for d in dependencies(t).into_iter().rev() {
+                        stack.push((d.as_str(), depth + 1));
+                    }
+                }
+            }
+        }
+    }
+
+    out.push_str("@endwbs\n");
+    Ok(out)
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::run::{NodeRun, Outcome, RunReport};
+
+// ReportError is why a saved RunReport couldn't be loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReportError {
+    // the input isn't JSON.
+    Parse(String),
+    // the input holds no "nodes" array.
+    NoNodes,
+    // the node at this index is missing a field, or holds the wrong type.
+    BadNode(usize),
+}
+
+impl ReportError {
+    // code names the variant, stable as LatticeError::code is.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ReportError::Parse(_) => "report_parse",
+            ReportError::NoNodes => "report_no_nodes",
+            ReportError::BadNode(_) => "report_bad_node",
+        }
+    }
+}
+
+impl fmt::Display for ReportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReportError::Parse(e) => write!(f, "report is not valid JSON: {}", e),
+            ReportError::NoNodes => write!(f, "report holds no node array"),
+            ReportError::BadNode(i) => write!(f, "node {} is malformed", i),
+        }
+    }
+}
+
+impl std::error::Error for ReportError {}
+
+fn outcome_name(o: Outcome) -> &'static str {
+    match o {
+        Outcome::Fulfilled => "fulfilled",
+        Outcome::Pending => "pending",
+        Outcome::Error => "error",
+        Outcome::Discarded => "discarded",
+        Outcome::TimedOut => "timed_out",
+    }
+}
+
+fn outcome_from(name: &str) -> Option<Outcome> {
+    match name {
+        "fulfilled" => Some(Outcome::Fulfilled),
+        "pending" => Some(Outcome::Pending),
+        "error" => Some(Outcome::Error),
+        "discarded" => Some(Outcome::Discarded),
+        "timed_out" => Some(Outcome::TimedOut),
+        _ => None,
+    }
+}
+
+fn node(v: &Value) -> Option<NodeRun> {
+    let log = match v.get("log") {
+        None | Some(Value::Null) => None,
+        Some(l) => Some(l.as_str()?.to_string()),
+    };
+    Some(NodeRun {
+        key: v.get("key")?.as_str()?.to_string(),
+        outcome: outcome_from(v.get("outcome")?.as_str()?)?,
+        attempts: v.get("attempts")?.as_u64()? as u32,
+        duration: Duration::from_millis(v.get("duration_ms")?.as_u64()?),
+        log,
+    })
+}
+
+impl RunReport {
+    // to_json writes the report as {"nodes": [...]}, each node's outcome
+    // in snake_case and its duration in whole milliseconds.
+    pub fn to_json(&self) -> String {
+        let nodes: Vec<Value> = self
+            .nodes
+            .iter()
+            .map(|n| {
+                json!({
+                    "key": n.key,
+                    "outcome": outcome_name(n.outcome),
+                    "attempts": n.attempts,
+                    "duration_ms": n.duration.as_millis() as u64,
+                    "log": n.log,
+                })
+            })
+            .collect();
+        json!({ "nodes": nodes }).to_string()
+    }
+
+    // from_json reads back what to_json wrote. Durations come back
+    // truncated to the millisecond.
+    pub fn from_json(s: &str) -> Result<RunReport, ReportError> {
+        let root: Value = serde_json::from_str(s).map_err(|e| ReportError::Parse(e.to_string()))?;
+        let raw = root
+            .get("nodes")
+            .and_then(|v| v.as_array())
+            .ok_or(ReportError::NoNodes)?;
+
+        let nodes = raw
+            .iter()
+            .enumerate()
+            .map(|(i, v)| node(v).ok_or(ReportError::BadNode(i)))
+            .collect::<Result<_, _>>()?;
+        Ok(RunReport { nodes })
+    }
+}
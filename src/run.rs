@@ -0,0 +1,546 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::LatticeError;
+use crate::{LatMachine, WriteNode};
+
+// RunError is why run_to_completion stopped early. Whatever ran before it
+// stays applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RunError<E> {
+    // the closure failed for key.
+    Task { key: String, error: E },
+    // update_value refused key's result.
+    Lattice(LatticeError),
+    // key's job panicked with message, see run_parallel and run_timed.
+    Panicked { key: String, message: String },
+}
+
+impl<E: fmt::Display> fmt::Display for RunError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::Task { key, error } => write!(f, "{} failed: {}", key, error),
+            RunError::Lattice(e) => e.fmt(f),
+            RunError::Panicked { key, message } => write!(f, "{} panicked: {}", key, message),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for RunError<E> {}
+
+// Outcome is how a single LatNode's run ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    // its result was applied and fulfilled it.
+    Fulfilled,
+    // its result was applied but left it pending.
+    Pending,
+    // the closure or update_value failed, stopping the run.
+    Error,
+    // it ran alongside a LatNode that failed, its result was dropped.
+    Discarded,
+    // every attempt outlived its timeout, it has been failed.
+    TimedOut,
+}
+
+impl Outcome {
+    // is_failure reports whether the LatNode itself went wrong.
+    pub fn is_failure(self) -> bool {
+        matches!(self, Outcome::Error | Outcome::TimedOut)
+    }
+}
+
+// NodeRun is one LatNode's entry in a RunReport.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeRun {
+    pub key: String,
+    pub outcome: Outcome,
+    // 1 unless a timed run retried it.
+    pub attempts: u32,
+    // wall time across every attempt.
+    pub duration: Duration,
+    // where the LatNode's logs can be found, see RunReport::set_log.
+    pub log: Option<String>,
+}
+
+// RunReport records what a run_to_completion call did, in the order
+// LatNodes finished, for comparing one run against the next.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunReport {
+    pub nodes: Vec<NodeRun>,
+}
+
+// Comparison is one key across two RunReports, see RunReport::compare.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comparison {
+    pub key: String,
+    pub before: Option<NodeRun>,
+    pub after: Option<NodeRun>,
+}
+
+impl Comparison {
+    // changed reports whether the key ran in only one of the runs, or
+    // ended differently or after a different number of attempts.
+    pub fn changed(&self) -> bool {
+        match (&self.before, &self.after) {
+            (Some(b), Some(a)) => b.outcome != a.outcome || b.attempts != a.attempts,
+            _ => true,
+        }
+    }
+}
+
+// Timing is a key that ran in both of two RunReports, see RunDiff.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Timing {
+    pub key: String,
+    pub before: Duration,
+    pub after: Duration,
+}
+
+// RunDiff is what regressed, or improved, from one run to a later one,
+// see RunReport::diff.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunDiff {
+    // biggest slowdown first, ties by key.
+    pub slower: Vec<Timing>,
+    // biggest speedup first, ties by key.
+    pub faster: Vec<Timing>,
+    // failed in the later run having not failed, or not run, before.
+    // Sorted.
+    pub newly_failed: Vec<String>,
+    // ran in both runs and ended differently, by key.
+    pub changed: Vec<Comparison>,
+}
+
+impl RunReport {
+    // keys lists the LatNodes run, in the order they finished.
+    pub fn keys(&self) -> Vec<&str> {
+        self.nodes.iter().map(|n| n.key.as_str()).collect()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&NodeRun> {
+        self.nodes.iter().find(|n| n.key == key)
+    }
+
+    // set_log points key's entry at reference, a path or URL say. False if
+    // key didn't run.
+    pub fn set_log(&mut self, key: &str, reference: String) -> bool {
+        match self.nodes.iter_mut().find(|n| n.key == key) {
+            None => false,
+            Some(n) => {
+                n.log = Some(reference);
+                true
+            }
+        }
+    }
+
+    // compare lines this report up against a later one, every key either
+    // ran sorted, so durations can be set side by side and changed picks
+    // out what ended differently.
+    pub fn compare(&self, later: &RunReport) -> Vec<Comparison> {
+        let mut keys: Vec<&String> = self
+            .nodes
+            .iter()
+            .chain(later.nodes.iter())
+            .map(|n| &n.key)
+            .collect();
+        keys.sort();
+        keys.dedup();
+
+        keys.into_iter()
+            .map(|k| Comparison {
+                key: k.clone(),
+                before: self.get(k).cloned(),
+                after: later.get(k).cloned(),
+            })
+            .collect()
+    }
+
+    // diff sums up how a later run of the same lattice went against this
+    // one. Durations only count as slower or faster when they moved by
+    // more than tolerance, so noise can be left out.
+    pub fn diff(&self, later: &RunReport, tolerance: Duration) -> RunDiff {
+        let mut d = RunDiff::default();
+        for c in self.compare(later) {
+            let (before, after) = match (&c.before, &c.after) {
+                (_, None) => continue,
+                (None, Some(a)) => {
+                    if a.outcome.is_failure() {
+                        d.newly_failed.push(c.key);
+                    }
+                    continue;
+                }
+                (Some(b), Some(a)) => (b, a),
+            };
+
+            let timing = Timing {
+                key: c.key.clone(),
+                before: before.duration,
+                after: after.duration,
+            };
+            if after.duration > before.duration + tolerance {
+                d.slower.push(timing);
+            } else if before.duration > after.duration + tolerance {
+                d.faster.push(timing);
+            }
+            if after.outcome.is_failure() && !before.outcome.is_failure() {
+                d.newly_failed.push(c.key.clone());
+            }
+            if before.outcome != after.outcome {
+                d.changed.push(c);
+            }
+        }
+
+        d.slower.sort_by(|a, b| {
+            (b.after - b.before)
+                .cmp(&(a.after - a.before))
+                .then_with(|| a.key.cmp(&b.key))
+        });
+        d.faster.sort_by(|a, b| {
+            (b.before - b.after)
+                .cmp(&(a.before - a.after))
+                .then_with(|| a.key.cmp(&b.key))
+        });
+        d
+    }
+}
+
+// Ran is a finished job: (key, result, attempts, duration).
+type Ran<U, E> = (String, Result<U, E>, u32, Duration);
+
+// Run tracks the LatNodes run so far.
+#[derive(Default)]
+struct Run {
+    report: RunReport,
+    seen: HashMap<String, ()>,
+}
+
+impl Run {
+    fn record(&mut self, key: String, outcome: Outcome, attempts: u32, duration: Duration) {
+        self.seen.insert(key.clone(), ());
+        self.report.nodes.push(NodeRun {
+            key,
+            outcome,
+            attempts,
+            duration,
+            log: None,
+        });
+    }
+}
+
+// wave lists the pending LatNodes ready to run, sorted: nothing left to
+// wait on, not failed, skipped or already run.
+fn wave<L, T, U>(l: &L, ran: &Run) -> Vec<String>
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+{
+    let mut keys: Vec<String> = l
+        .read_pending()
+        .iter()
+        .filter(|(k, t)| {
+            !t.is_blocked()
+                && t.skipped().is_none()
+                && !l.read_failed().contains_key(*k)
+                && !ran.seen.contains_key(*k)
+        })
+        .map(|(k, _)| k.clone())
+        .collect();
+    keys.sort();
+    keys
+}
+
+// apply hands each result to update_value in order, stopping at the first
+// failure. Results after it are recorded as Discarded.
+fn apply<L, T, U, E>(l: &mut L, results: Vec<Ran<U, E>>, ran: &mut Run) -> Result<(), RunError<E>>
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+{
+    let mut failed = None;
+    for (key, result, attempts, duration) in results {
+        if failed.is_some() {
+            ran.record(key, Outcome::Discarded, attempts, duration);
+            continue;
+        }
+
+        let applied = match result {
+            Err(error) => Err(RunError::Task {
+                key: key.clone(),
+                error,
+            }),
+            Ok(u) => l.update_value(key.clone(), u).map_err(RunError::Lattice),
+        };
+        let outcome = match applied {
+            Err(e) => {
+                failed = Some(e);
+                Outcome::Error
+            }
+            Ok(()) if l.read_fulfilled().contains_key(&key) => Outcome::Fulfilled,
+            Ok(()) => Outcome::Pending,
+        };
+        ran.record(key, outcome, attempts, duration);
+    }
+
+    match failed {
+        None => Ok(()),
+        Some(e) => Err(e),
+    }
+}
+
+// run works through l wave by wave: every ready LatNode is handed to f,
+// in key order, and its result applied with update_value, which fulfills
+// it (and cascades) when the result completes it. Each LatNode runs at
+// most once, so one left pending by its result, or by a Manual
+// FulfillPolicy, is not retried. The report comes back either way.
+pub fn run<L, T, U, E, F>(l: &mut L, mut f: F) -> Result<RunReport, (RunReport, RunError<E>)>
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+    F: FnMut(&str, &T) -> Result<U, E>,
+{
+    let mut ran = Run::default();
+    loop {
+        let keys = wave(l, &ran);
+        if keys.is_empty() {
+            return Ok(ran.report);
+        }
+
+        for key in keys {
+            // an earlier result in the wave may have moved it on.
+            let start = Instant::now();
+            let result = match l.read_pending().get(&key) {
+                None => continue,
+                Some(t) => f(&key, t),
+            };
+            let done = (key, result, 1, start.elapsed());
+            if let Err(e) = apply(l, vec![done], &mut ran) {
+                return Err((ran.report, e));
+            }
+        }
+    }
+}
+
+// run_parallel is run with each wave split across up to threads scoped
+// threads. Results are applied in key order once the whole wave is back,
+// so on an error the LatNodes after it in the wave have run but aren't
+// applied. A LatNode whose f panics stops the run with RunError::Panicked
+// once the rest of its wave is applied.
+pub fn run_parallel<L, T, U, E, F>(
+    l: &mut L,
+    threads: usize,
+    f: F,
+) -> Result<RunReport, (RunReport, RunError<E>)>
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U> + Sync,
+    U: Send,
+    E: Send,
+    F: Fn(&str, &T) -> Result<U, E> + Sync,
+{
+    let mut ran = Run::default();
+    loop {
+        let keys = wave(l, &ran);
+        if keys.is_empty() {
+            return Ok(ran.report);
+        }
+
+        let pending = l.read_pending();
+        let chunk = keys.len().div_ceil(threads.max(1));
+        let outcomes: Vec<_> = thread::scope(|s| {
+            let handles: Vec<_> = keys
+                .chunks(chunk)
+                .map(|keys| {
+                    let f = &f;
+                    s.spawn(move || {
+                        keys.iter()
+                            .map(|k| {
+                                let start = Instant::now();
+                                let result =
+                                    panic::catch_unwind(AssertUnwindSafe(|| f(k, &pending[k])))
+                                        .map_err(|payload| panic_message(&*payload));
+                                (k.clone(), result, start.elapsed())
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                // f's panics are caught above, so joining can't fail.
+                .flat_map(|h| h.join().unwrap_or_default())
+                .collect()
+        });
+
+        let mut results = Vec::new();
+        // the first LatNode to panic, the rest of the wave is still
+        // applied.
+        let mut panicked = None;
+        for (key, result, duration) in outcomes {
+            match result {
+                Ok(result) => results.push((key, result, 1, duration)),
+                Err(message) => {
+                    ran.record(key.clone(), Outcome::Error, 1, duration);
+                    panicked.get_or_insert(RunError::Panicked { key, message });
+                }
+            }
+        }
+        if let Err(e) = apply(l, results, &mut ran) {
+            return Err((ran.report, e));
+        }
+        if let Some(e) = panicked {
+            return Err((ran.report, e));
+        }
+    }
+}
+
+// Timeout is reported each time a LatNode's job outlives
+// NodeType::timeout and is abandoned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Timeout {
+    pub key: String,
+    pub limit: Duration,
+    // 1 for the first run, counting retries.
+    pub attempt: u32,
+    // whether the job is being run again, otherwise key has been failed.
+    pub retrying: bool,
+}
+
+// run_timed is run_parallel for jobs that may hang. prepare turns each
+// ready LatNode into a job that runs on its own thread, so one that
+// outlives the LatNode's NodeType::timeout can be abandoned: left running
+// with its result ignored. An abandoned job is prepared and run again up
+// to retries times, after that the LatNode is failed, see
+// LatMachine::fail. on_timeout hears of every abandoned job. A job that
+// panics stops the run with RunError::Panicked once the rest of its wave
+// is applied.
+pub fn run_timed<L, T, U, E, P, J>(
+    l: &mut L,
+    retries: u32,
+    mut prepare: P,
+    on_timeout: &mut dyn FnMut(&Timeout),
+) -> Result<RunReport, (RunReport, RunError<E>)>
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+    U: Send + 'static,
+    E: Send + 'static,
+    P: FnMut(&str, &T) -> J,
+    J: FnOnce() -> Result<U, E> + Send + 'static,
+{
+    // (key, attempt, result), Err with the message if the job panicked.
+    type Done<U, E> = (String, u32, Result<Result<U, E>, String>);
+
+    let mut ran = Run::default();
+    loop {
+        let keys = wave(l, &ran);
+        if keys.is_empty() {
+            return Ok(ran.report);
+        }
+
+        let (tx, rx) = mpsc::channel::<Done<U, E>>();
+        // key -> (attempt, deadline)
+        let mut running = HashMap::<String, (u32, Option<Instant>)>::new();
+        // key -> when its first attempt started
+        let mut started = HashMap::<String, Instant>::new();
+        let mut spawn = |l: &L, key: &str, attempt: u32| {
+            let t = &l.read_pending()[key];
+            let deadline = t.timeout().map(|d| Instant::now() + d);
+            let job = prepare(key, t);
+            let (tx, k) = (tx.clone(), key.to_string());
+            thread::spawn(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(job))
+                    .map_err(|payload| panic_message(&*payload));
+                // the receiver is gone if the job was abandoned.
+                let _ = tx.send((k, attempt, result));
+            });
+            deadline
+        };
+        for key in &keys {
+            started.insert(key.clone(), Instant::now());
+            let deadline = spawn(l, key, 1);
+            running.insert(key.clone(), (1, deadline));
+        }
+
+        let mut results = Vec::new();
+        // the first job to panic, the rest of the wave is still applied.
+        let mut panicked = None;
+        while !running.is_empty() {
+            let next = running.values().filter_map(|(_, d)| *d).min();
+            let done = match next {
+                None => rx.recv().ok(),
+                Some(d) => rx
+                    .recv_timeout(d.saturating_duration_since(Instant::now()))
+                    .ok(),
+            };
+            if let Some((key, attempt, result)) = done {
+                if running.get(&key).is_some_and(|(a, _)| *a == attempt) {
+                    running.remove(&key);
+                    let duration = started[&key].elapsed();
+                    match result {
+                        Ok(result) => results.push((key, result, attempt, duration)),
+                        Err(message) => {
+                            ran.record(key.clone(), Outcome::Error, attempt, duration);
+                            panicked.get_or_insert(RunError::Panicked { key, message });
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let now = Instant::now();
+            let mut overdue: Vec<String> = running
+                .iter()
+                .filter(|(_, (_, d))| d.is_some_and(|d| d <= now))
+                .map(|(k, _)| k.clone())
+                .collect();
+            overdue.sort();
+            for key in overdue {
+                let (attempt, _) = running.remove(&key).unwrap();
+                let limit = l.read_pending()[&key].timeout().unwrap_or_default();
+                let retrying = attempt <= retries;
+                on_timeout(&Timeout {
+                    key: key.clone(),
+                    limit,
+                    attempt,
+                    retrying,
+                });
+                if retrying {
+                    let deadline = spawn(l, &key, attempt + 1);
+                    running.insert(key, (attempt + 1, deadline));
+                } else {
+                    let duration = started[&key].elapsed();
+                    ran.record(key.clone(), Outcome::TimedOut, attempt, duration);
+                    let reason = format!("timed out after {:?}", limit);
+                    if let Err(e) = l.fail(key, reason) {
+                        return Err((ran.report, RunError::Lattice(e)));
+                    }
+                }
+            }
+        }
+
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        if let Err(e) = apply(l, results, &mut ran) {
+            return Err((ran.report, e));
+        }
+        if let Some(e) = panicked {
+            return Err((ran.report, e));
+        }
+    }
+}
+
+// panic_message is what a job panicked with, when it was a string.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(s) => s.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(s) => s.clone(),
+            None => "non-string panic payload".to_string(),
+        },
+    }
+}
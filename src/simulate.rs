@@ -0,0 +1,167 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::{blocked_past, LatMachine, WriteNode};
+
+// Slot is one LatNode's run in a simulation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Slot {
+    pub key: String,
+    pub worker: usize,
+    pub start: u64,
+    pub end: u64,
+}
+
+// Simulation is the outcome of executing a lattice's pending LatNodes on a
+// pool of workers, see LatMachine::simulate_execution.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Simulation {
+    // when the last LatNode finished, 0 if none ran.
+    pub makespan: u64,
+    // time each worker spent busy, by worker index.
+    pub busy: Vec<u64>,
+    // busy over makespan, by worker index.
+    pub utilization: Vec<f64>,
+    // every LatNode run, in the order they started.
+    pub schedule: Vec<Slot>,
+    // pending LatNodes that could never start: they wait on a cycle or a
+    // key the lattice doesn't hold. Sorted.
+    pub unscheduled: Vec<String>,
+}
+
+// run simulates workers executing every pending LatNode of l, each taking
+// durations(key, node) time units. A LatNode may start once everything it
+// depends on has finished, fulfilled LatNodes count as finished from the
+// start. A quorum lets a LatNode start once enough of its members have
+// finished, soft dependencies never hold it. Whenever a worker is free it takes the LatNode that has been
+// ready longest, ties broken by key, so runs are repeatable.
+pub fn run<L, T, U, F>(l: &L, workers: usize, mut durations: F) -> Simulation
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+    F: FnMut(&str, &T) -> u64,
+{
+    let pending = l.read_pending();
+    let mut waiting_on = HashMap::<&str, Vec<&str>>::new();
+    let mut outstanding = HashMap::<&str, ()>::new();
+    let mut queued = HashMap::<&str, ()>::new();
+    // past holds the keys that have finished so far.
+    let mut past = HashMap::<String, ()>::new();
+    // (ready at, key)
+    let mut ready = BinaryHeap::new();
+    for (k, t) in pending {
+        outstanding.insert(k, ());
+        if !t.is_blocked() {
+            queued.insert(k, ());
+            ready.push(Reverse((0, k.as_str())));
+        }
+        for d in t.depends_on().keys() {
+            waiting_on.entry(d).or_default().push(k);
+        }
+    }
+
+    let mut sim = Simulation {
+        busy: vec![0; workers],
+        ..Simulation::default()
+    };
+    let mut idle: BinaryHeap<Reverse<usize>> = (0..workers).map(Reverse).collect();
+    // (end, worker, key)
+    let mut running = BinaryHeap::new();
+    let mut now = 0;
+    loop {
+        while !idle.is_empty() && !ready.is_empty() {
+            let Reverse(worker) = idle.pop().unwrap();
+            let Reverse((_, k)) = ready.pop().unwrap();
+            let end = now + durations(k, &pending[k]);
+            sim.busy[worker] += end - now;
+            sim.schedule.push(Slot {
+                key: k.to_string(),
+                worker,
+                start: now,
+                end,
+            });
+            running.push(Reverse((end, worker, k)));
+        }
+
+        let Reverse((end, worker, k)) = match running.pop() {
+            None => break,
+            Some(next) => next,
+        };
+        now = end;
+        idle.push(Reverse(worker));
+        outstanding.remove(k);
+        past.insert(k.to_string(), ());
+        for r in waiting_on.get(k).into_iter().flatten() {
+            let t = &pending[*r];
+            if !queued.contains_key(r)
+                && !blocked_past(t.depends_on(), t.fulfilled_by(), t.quorums(), &past)
+            {
+                queued.insert(r, ());
+                ready.push(Reverse((now, *r)));
+            }
+        }
+    }
+
+    sim.makespan = now;
+    sim.utilization = sim
+        .busy
+        .iter()
+        .map(|b| match now {
+            0 => 0.0,
+            _ => *b as f64 / now as f64,
+        })
+        .collect();
+    sim.unscheduled = outstanding.keys().map(|k| k.to_string()).collect();
+    sim.unscheduled.sort();
+    sim
+}
+
+// Sensitivity is how far one LatNode's estimate moves the makespan, see
+// LatMachine::duration_sensitivity.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sensitivity {
+    pub key: String,
+    // makespan with the LatNode delta longer, less the baseline makespan.
+    // Greedy scheduling can make this negative.
+    pub shift: i64,
+}
+
+// sensitivity reruns the simulation once per pending LatNode with that
+// LatNode's duration delta longer, ranking them by how far the makespan
+// moves, biggest first and ties by key. LatNodes that never start aren't
+// ranked. Costs a full simulation per LatNode.
+pub fn sensitivity<L, T, U, F>(
+    l: &L,
+    workers: usize,
+    mut durations: F,
+    delta: u64,
+) -> Vec<Sensitivity>
+where
+    L: LatMachine<T, U>,
+    T: WriteNode<U>,
+    F: FnMut(&str, &T) -> u64,
+{
+    let base: HashMap<&str, u64> = l
+        .read_pending()
+        .iter()
+        .map(|(k, t)| (k.as_str(), durations(k, t)))
+        .collect();
+    let baseline = run(l, workers, |k, _| base[k]);
+
+    let mut out: Vec<Sensitivity> = baseline
+        .schedule
+        .iter()
+        .map(|slot| {
+            let perturbed = run(l, workers, |k, _| match k == slot.key {
+                true => base[k] + delta,
+                false => base[k],
+            });
+            Sensitivity {
+                key: slot.key.clone(),
+                shift: perturbed.makespan as i64 - baseline.makespan as i64,
+            }
+        })
+        .collect();
+    out.sort_by(|a, b| b.shift.cmp(&a.shift).then_with(|| a.key.cmp(&b.key)));
+    out
+}
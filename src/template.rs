@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use crate::changeset::{Changeset, Preview};
+use crate::{LatMachine, WriteNode};
+
+// Template is a named, versioned sub-lattice shape: keys and the keys they
+// depend on, without any node data. Instantiating it stamps out a fresh
+// copy under a key prefix.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    name: String,
+    version: u32,
+    nodes: Vec<(String, Vec<String>)>,
+}
+
+impl Template {
+    pub fn new(name: String, version: u32) -> Self {
+        Template {
+            name,
+            version,
+            nodes: Vec::new(),
+        }
+    }
+
+    // add_node adds key, depending on each of depends_on. Dependencies on
+    // keys the template doesn't hold are left as they are when
+    // instantiated, so a template can hang off LatNodes already in the
+    // target lattice.
+    pub fn add_node(&mut self, key: String, depends_on: Vec<String>) {
+        self.nodes.push((key, depends_on));
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn nodes(&self) -> &[(String, Vec<String>)] {
+        &self.nodes
+    }
+
+    // changeset stages a copy of the template with every key prefixed by
+    // prefix. build is given each prefixed key and must return base data
+    // whose uuid is that key.
+    pub fn changeset<T, U, F>(&self, prefix: &str, mut build: F) -> Changeset<T>
+    where
+        T: WriteNode<U>,
+        F: FnMut(&str) -> U,
+    {
+        let local: HashMap<&str, ()> = self.nodes.iter().map(|(k, _)| (k.as_str(), ())).collect();
+        let rename = |k: &String| {
+            if local.contains_key(k.as_str()) {
+                format!("{}{}", prefix, k)
+            } else {
+                k.clone()
+            }
+        };
+
+        let mut cs = Changeset::new();
+        for (key, deps) in &self.nodes {
+            let key = rename(key);
+            let deps = deps.iter().map(rename).collect();
+            cs.stage(T::new(build(&key), deps, Vec::new()));
+        }
+        cs
+    }
+
+    // instantiate commits changeset(prefix, build) to l, returning the
+    // preview's problems if it can't.
+    pub fn instantiate<L, T, U, F>(&self, l: &mut L, prefix: &str, build: F) -> Result<(), Preview>
+    where
+        L: LatMachine<T, U>,
+        T: WriteNode<U>,
+        F: FnMut(&str) -> U,
+    {
+        self.changeset(prefix, build)
+            .commit(l)
+            .map_err(|(_, preview)| preview)
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::import::ImportReport;
+use crate::{BasicLattice, BasicNode, LatMachine, NodeType, ReadNode};
+
+// Issue is the base data an issue tracker export is imported as.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Issue {
+    pub key: String,
+    pub status: String,
+    pub summary: String,
+    // whether status is one of FieldMapping::completed.
+    pub completed: bool,
+}
+
+impl NodeType for Issue {
+    fn uuid(&self) -> String {
+        self.key.clone()
+    }
+
+    fn is_completed(&self) -> bool {
+        self.completed
+    }
+}
+
+// FieldMapping says where an export keeps each field, as JSON pointers
+// (RFC 6901). issues points at the array of issues from the export's
+// root, every other pointer is relative to a single issue, or to a single
+// link for the link_ ones. The default reads a Jira search export.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldMapping {
+    pub issues: String,
+    pub key: String,
+    pub status: String,
+    pub summary: String,
+    // statuses that count as completed.
+    pub completed: Vec<String>,
+    pub links: String,
+    pub link_type: String,
+    // link types that block, others are ignored.
+    pub blocking_types: Vec<String>,
+    // the issue this one blocks.
+    pub link_blocks: String,
+    // the issue this one is blocked by.
+    pub link_blocked_by: String,
+}
+
+impl Default for FieldMapping {
+    fn default() -> Self {
+        FieldMapping {
+            issues: "/issues".to_string(),
+            key: "/key".to_string(),
+            status: "/fields/status/name".to_string(),
+            summary: "/fields/summary".to_string(),
+            completed: vec!["Done".to_string(), "Closed".to_string()],
+            links: "/fields/issuelinks".to_string(),
+            link_type: "/type/name".to_string(),
+            blocking_types: vec!["Blocks".to_string()],
+            link_blocks: "/outwardIssue/key".to_string(),
+            link_blocked_by: "/inwardIssue/key".to_string(),
+        }
+    }
+}
+
+// TrackerError is why an export couldn't be read at all.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TrackerError {
+    // the export isn't JSON.
+    Parse(String),
+    // FieldMapping::issues doesn't point at an array.
+    NoIssues,
+    // the issue at this index has no string at FieldMapping::key.
+    MissingKey(usize),
+}
+
+impl TrackerError {
+    // code names the variant, stable as LatticeError::code is.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TrackerError::Parse(_) => "tracker_parse",
+            TrackerError::NoIssues => "tracker_no_issues",
+            TrackerError::MissingKey(_) => "tracker_missing_key",
+        }
+    }
+}
+
+impl fmt::Display for TrackerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrackerError::Parse(e) => write!(f, "export is not valid JSON: {}", e),
+            TrackerError::NoIssues => write!(f, "export holds no issue array"),
+            TrackerError::MissingKey(i) => write!(f, "issue {} has no key", i),
+        }
+    }
+}
+
+impl std::error::Error for TrackerError {}
+
+fn text(v: &Value, pointer: &str) -> Option<String> {
+    v.pointer(pointer)?.as_str().map(|s| s.to_string())
+}
+
+// import reads an issue tracker export into a lattice, each issue made to
+// depend on the issues blocking it. Problems in the links (issues outside
+// the export, duplicate keys, cycles) are dropped and reported as by
+// BasicLattice::from_node_list_lenient.
+pub fn import(
+    export: &str,
+    mapping: &FieldMapping,
+) -> Result<(BasicLattice<BasicNode<Issue>>, ImportReport), TrackerError> {
+    let root: Value =
+        serde_json::from_str(export).map_err(|e| TrackerError::Parse(e.to_string()))?;
+    let raw = root
+        .pointer(&mapping.issues)
+        .and_then(|v| v.as_array())
+        .ok_or(TrackerError::NoIssues)?;
+
+    let mut issues = Vec::with_capacity(raw.len());
+    // key -> (depends_on, required_by)
+    let mut edges = HashMap::<String, (Vec<String>, Vec<String>)>::new();
+    for (i, v) in raw.iter().enumerate() {
+        let key = text(v, &mapping.key).ok_or(TrackerError::MissingKey(i))?;
+        let status = text(v, &mapping.status).unwrap_or_default();
+        let links = v.pointer(&mapping.links).and_then(|l| l.as_array());
+        for link in links.into_iter().flatten() {
+            let blocking =
+                text(link, &mapping.link_type).is_some_and(|t| mapping.blocking_types.contains(&t));
+            if !blocking {
+                continue;
+            }
+
+            let (requires, is_required) = match (
+                text(link, &mapping.link_blocks),
+                text(link, &mapping.link_blocked_by),
+            ) {
+                (Some(blocks), _) => (blocks, key.clone()),
+                (None, Some(blocked_by)) => (key.clone(), blocked_by),
+                (None, None) => continue,
+            };
+            let (depends_on, _) = edges.entry(requires.clone()).or_default();
+            depends_on.push(is_required.clone());
+            let (_, required_by) = edges.entry(is_required).or_default();
+            required_by.push(requires);
+        }
+
+        issues.push(Issue {
+            completed: mapping.completed.contains(&status),
+            summary: text(v, &mapping.summary).unwrap_or_default(),
+            key,
+            status,
+        });
+    }
+
+    // Links are usually listed on both issues, keep each once.
+    for (depends_on, required_by) in edges.values_mut() {
+        depends_on.sort();
+        depends_on.dedup();
+        required_by.sort();
+        required_by.dedup();
+    }
+
+    let nodes = issues
+        .into_iter()
+        .map(|issue| {
+            let (depends_on, required_by) = edges.remove(&issue.key).unwrap_or_default();
+            BasicNode::new(issue, depends_on, required_by)
+        })
+        .collect();
+    Ok(BasicLattice::from_node_list_lenient(nodes))
+}
+
+// Baseline is what the tracker holds, captured from a lattice straight
+// after import, for export to diff against later.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Baseline {
+    statuses: HashMap<String, String>,
+    // (blocked, blocker)
+    links: HashMap<(String, String), ()>,
+}
+
+// TrackerOp is one change to push back to the tracker.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TrackerOp {
+    SetStatus { key: String, status: String },
+    AddLink { blocker: String, blocked: String },
+    RemoveLink { blocker: String, blocked: String },
+}
+
+// links lists the (blocked, blocker) pairs t holds, done or not.
+fn links(t: &BasicNode<Issue>) -> impl Iterator<Item = (String, String)> + '_ {
+    t.depends_on()
+        .keys()
+        .chain(t.fulfilled_by().keys())
+        .map(move |d| (t.uuid(), d.clone()))
+}
+
+impl Baseline {
+    pub fn of<L>(l: &L) -> Self
+    where
+        L: LatMachine<BasicNode<Issue>, Issue>,
+    {
+        let mut b = Baseline::default();
+        for t in l.read_pending().values().chain(l.read_fulfilled().values()) {
+            b.statuses.insert(t.uuid(), t.base_data().status.clone());
+            for link in links(t) {
+                b.links.insert(link, ());
+            }
+        }
+        b
+    }
+}
+
+// export diffs l against baseline, returning the status and link changes
+// that bring the tracker in line, sorted. An issue fulfilled in the
+// lattice without a completed status is moved to the first of
+// mapping.completed. Issues the baseline doesn't hold, and links to them,
+// are left out: they would have to be created in the tracker first.
+pub fn export<L>(baseline: &Baseline, l: &L, mapping: &FieldMapping) -> Vec<TrackerOp>
+where
+    L: LatMachine<BasicNode<Issue>, Issue>,
+{
+    let mut ops = Vec::new();
+    let nodes = l
+        .read_pending()
+        .values()
+        .map(|t| (t, false))
+        .chain(l.read_fulfilled().values().map(|t| (t, true)));
+
+    let mut current = HashMap::<(String, String), ()>::new();
+    for (t, fulfilled) in nodes {
+        let key = t.uuid();
+        let was = match baseline.statuses.get(&key) {
+            None => continue,
+            Some(was) => was,
+        };
+
+        let mut status = &t.base_data().status;
+        if fulfilled && !mapping.completed.contains(status) {
+            if let Some(done) = mapping.completed.first() {
+                status = done;
+            }
+        }
+        if status != was {
+            ops.push(TrackerOp::SetStatus {
+                key,
+                status: status.clone(),
+            });
+        }
+
+        for link in links(t) {
+            if baseline.statuses.contains_key(&link.1) {
+                current.insert(link, ());
+            }
+        }
+    }
+
+    for (blocked, blocker) in current.keys() {
+        if !baseline
+            .links
+            .contains_key(&(blocked.clone(), blocker.clone()))
+        {
+            ops.push(TrackerOp::AddLink {
+                blocker: blocker.clone(),
+                blocked: blocked.clone(),
+            });
+        }
+    }
+    for ((blocked, blocker), _) in baseline.links.iter() {
+        let gone = !current.contains_key(&(blocked.clone(), blocker.clone()));
+        // issues removed from the lattice stay in the tracker, links and all.
+        if gone && l.read_node(blocked).is_some() && l.read_node(blocker).is_some() {
+            ops.push(TrackerOp::RemoveLink {
+                blocker: blocker.clone(),
+                blocked: blocked.clone(),
+            });
+        }
+    }
+
+    ops.sort();
+    ops
+}
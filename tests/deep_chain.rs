@@ -0,0 +1,85 @@
+use lattice_machines::{BasicLattice, BasicNode, LatMachine, NodeType, ReadNode};
+
+const DEPTH: usize = 100_000;
+
+#[derive(Clone, Debug)]
+struct Step {
+    id: String,
+    completed: bool,
+}
+
+impl NodeType for Step {
+    fn uuid(&self) -> String {
+        self.id.clone()
+    }
+
+    fn is_completed(&self) -> bool {
+        self.completed
+    }
+}
+
+fn key(i: usize) -> String {
+    format!("s{}", i)
+}
+
+// chain builds s0 <- s1 <- ... <- s{DEPTH-1}, every link completed but the
+// first. With sidecars each link is also required by a pending side node,
+// so no link has a single dependent.
+fn chain(sidecars: bool) -> BasicLattice<BasicNode<Step>> {
+    let mut nodes = Vec::new();
+    for i in 0..DEPTH {
+        let depends_on = if i == 0 { vec![] } else { vec![key(i - 1)] };
+        let mut required_by = if i + 1 < DEPTH {
+            vec![key(i + 1)]
+        } else {
+            vec![]
+        };
+        if sidecars {
+            let side = format!("side{}", i);
+            required_by.push(side.clone());
+            nodes.push(BasicNode::new(
+                Step {
+                    id: side,
+                    completed: false,
+                },
+                vec![key(i)],
+                vec![],
+            ));
+        }
+
+        nodes.push(BasicNode::new(
+            Step {
+                id: key(i),
+                completed: i > 0,
+            },
+            depends_on,
+            required_by,
+        ));
+    }
+    BasicLattice::from_node_list(nodes).unwrap()
+}
+
+fn fulfill_whole_chain(sidecars: bool) {
+    let mut l = chain(sidecars);
+    assert_eq!(l.read_fulfilled().len(), 0);
+
+    l.fulfill(key(0)).unwrap();
+
+    assert_eq!(l.read_fulfilled().len(), DEPTH);
+    let pending = if sidecars { DEPTH } else { 0 };
+    assert_eq!(l.read_pending().len(), pending);
+    let log = l.fulfillment_order();
+    assert_eq!(log.len(), DEPTH);
+    assert_eq!(log[DEPTH - 1].key, key(DEPTH - 1));
+    assert_eq!(log[DEPTH - 1].triggered_by, Some(key(DEPTH - 2)));
+}
+
+#[test]
+fn fulfill_cascades_down_a_100k_chain() {
+    fulfill_whole_chain(false);
+}
+
+#[test]
+fn fulfill_cascades_down_a_100k_chain_with_fan_out() {
+    fulfill_whole_chain(true);
+}
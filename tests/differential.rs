@@ -0,0 +1,26 @@
+mod reference;
+
+// xorshift64, enough randomness to drive the harness without pulling in
+// a dependency.
+fn bytes(seed: u64, len: usize) -> Vec<u8> {
+    let mut x = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect()
+}
+
+#[test]
+fn random_operation_sequences_match_reference() {
+    for seed in 0..500 {
+        let input = bytes(seed, 32 + (seed as usize % 96));
+        let (spec, ops) = reference::decode(&input);
+        if let Err(e) = reference::run(&spec, &ops) {
+            panic!("seed {}: {}\nspec: {:?}\nops: {:?}", seed, e, spec, ops);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+// An obviously-correct reference model of a lattice machine, plus a
+// driver that applies the same operations to it and to a BasicLattice
+// and compares the two. Shared by tests/differential.rs and the fuzz
+// targets under fuzz/.
+#![allow(dead_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use lattice_machines::{BasicLattice, BasicNode, LatMachine, NodeType, ReadNode};
+
+#[derive(Clone, Debug)]
+pub struct Item {
+    pub id: String,
+    pub completed: bool,
+}
+
+impl NodeType for Item {
+    fn uuid(&self) -> String {
+        self.id.clone()
+    }
+
+    fn is_completed(&self) -> bool {
+        self.completed
+    }
+}
+
+// A generated lattice: node i may only depend on nodes before it, so
+// every spec is acyclic.
+#[derive(Clone, Debug)]
+pub struct Spec {
+    pub completed: Vec<bool>,
+    pub deps: Vec<BTreeSet<usize>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+    // LatMachine::fulfill on the node.
+    Fulfill(usize),
+    // LatMachine::update_value with completed data.
+    Complete(usize),
+}
+
+pub fn key(i: usize) -> String {
+    format!("n{}", i)
+}
+
+// decode turns arbitrary bytes into a spec and an operation sequence,
+// reading zeroes once the input runs out.
+pub fn decode(bytes: &[u8]) -> (Spec, Vec<Op>) {
+    let mut it = bytes.iter().copied();
+    let mut next = || it.next().unwrap_or(0);
+
+    let n = 1 + next() as usize % 24;
+    let mut spec = Spec {
+        completed: Vec::with_capacity(n),
+        deps: Vec::with_capacity(n),
+    };
+
+    for i in 0..n {
+        spec.completed.push(next() & 1 == 1);
+        let mask = next();
+        let mut deps = BTreeSet::new();
+        for bit in 0..8 {
+            if mask & (1 << bit) != 0 && bit < i {
+                deps.insert(i - 1 - bit);
+            }
+        }
+        spec.deps.push(deps);
+    }
+
+    let mut ops = Vec::new();
+    for b in bytes.iter().skip(1 + 2 * n) {
+        let i = (*b >> 1) as usize % n;
+        ops.push(if b & 1 == 0 {
+            Op::Fulfill(i)
+        } else {
+            Op::Complete(i)
+        });
+    }
+
+    (spec, ops)
+}
+
+// The reference: a fulfilled set recomputed by brute force after every
+// operation. A node is fulfilled once every dependency is fulfilled and it
+// was either fulfilled explicitly or is completed. Fulfilling a node still
+// waiting on a dependency fails.
+pub struct Model {
+    pub deps: Vec<BTreeSet<usize>>,
+    pub completed: Vec<bool>,
+    pub fulfilled: Vec<bool>,
+}
+
+impl Model {
+    pub fn new(spec: &Spec) -> Self {
+        let mut m = Model {
+            deps: spec.deps.clone(),
+            completed: spec.completed.clone(),
+            fulfilled: vec![false; spec.deps.len()],
+        };
+        m.settle();
+        m
+    }
+
+    fn settle(&mut self) {
+        loop {
+            let mut changed = false;
+            for i in 0..self.deps.len() {
+                if !self.fulfilled[i]
+                    && self.completed[i]
+                    && self.deps[i].iter().all(|d| self.fulfilled[*d])
+                {
+                    self.fulfilled[i] = true;
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                return;
+            }
+        }
+    }
+
+    pub fn apply(&mut self, op: Op) -> Result<(), ()> {
+        let i = match op {
+            Op::Fulfill(i) | Op::Complete(i) => i,
+        };
+
+        if self.fulfilled[i] {
+            return Err(());
+        }
+
+        let ready = self.deps[i].iter().all(|d| self.fulfilled[*d]);
+        match op {
+            Op::Fulfill(_) if !ready => return Err(()),
+            Op::Fulfill(_) => self.fulfilled[i] = true,
+            Op::Complete(_) => self.completed[i] = true,
+        }
+        self.settle();
+        Ok(())
+    }
+}
+
+pub fn build(spec: &Spec) -> BasicLattice<BasicNode<Item>> {
+    let n = spec.deps.len();
+    let mut required_by = vec![Vec::new(); n];
+    for (i, deps) in spec.deps.iter().enumerate() {
+        for d in deps {
+            required_by[*d].push(key(i));
+        }
+    }
+
+    let nodes = (0..n)
+        .map(|i| {
+            BasicNode::new(
+                Item {
+                    id: key(i),
+                    completed: spec.completed[i],
+                },
+                spec.deps[i].iter().map(|d| key(*d)).collect(),
+                required_by[i].clone(),
+            )
+        })
+        .collect();
+
+    BasicLattice::from_node_list(nodes).unwrap()
+}
+
+fn keys(m: &std::collections::HashMap<String, ()>) -> BTreeSet<String> {
+    m.keys().cloned().collect()
+}
+
+// compare checks every node of the lattice against the model.
+pub fn compare(model: &Model, lat: &BasicLattice<BasicNode<Item>>) -> Result<(), String> {
+    let n = model.deps.len();
+    let pending = LatMachine::<BasicNode<Item>, Item>::read_pending(lat);
+    let fulfilled = LatMachine::<BasicNode<Item>, Item>::read_fulfilled(lat);
+
+    if pending.len() + fulfilled.len() != n {
+        return Err(format!(
+            "expected {} nodes, lattice holds {} pending and {} fulfilled",
+            n,
+            pending.len(),
+            fulfilled.len()
+        ));
+    }
+
+    let mut dependents = BTreeMap::<usize, BTreeSet<String>>::new();
+    for (i, deps) in model.deps.iter().enumerate() {
+        for d in deps {
+            dependents.entry(*d).or_default().insert(key(i));
+        }
+    }
+
+    for i in 0..n {
+        let k = key(i);
+        let node = match (pending.get(&k), fulfilled.get(&k), model.fulfilled[i]) {
+            (Some(node), None, false) | (None, Some(node), true) => node,
+            (p, f, want) => {
+                return Err(format!(
+                    "{}: pending={} fulfilled={} but model fulfilled={}",
+                    k,
+                    p.is_some(),
+                    f.is_some(),
+                    want
+                ))
+            }
+        };
+
+        let (waiting, done): (BTreeSet<usize>, BTreeSet<usize>) =
+            model.deps[i].iter().partition(|d| !model.fulfilled[**d]);
+        let waiting: BTreeSet<String> = waiting.into_iter().map(key).collect();
+        let done: BTreeSet<String> = done.into_iter().map(key).collect();
+
+        if keys(node.depends_on()) != waiting {
+            return Err(format!(
+                "{}: depends_on {:?}, model {:?}",
+                k,
+                keys(node.depends_on()),
+                waiting
+            ));
+        }
+
+        if keys(node.fulfilled_by()) != done {
+            return Err(format!(
+                "{}: fulfilled_by {:?}, model {:?}",
+                k,
+                keys(node.fulfilled_by()),
+                done
+            ));
+        }
+
+        let want = dependents.remove(&i).unwrap_or_default();
+        if keys(node.required_by()) != want {
+            return Err(format!(
+                "{}: required_by {:?}, model {:?}",
+                k,
+                keys(node.required_by()),
+                want
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// run applies ops to both implementations, comparing after every step.
+pub fn run(spec: &Spec, ops: &[Op]) -> Result<(), String> {
+    let mut model = Model::new(spec);
+    let mut lat = build(spec);
+    compare(&model, &lat).map_err(|e| format!("after from_node_list: {}", e))?;
+
+    for (step, op) in ops.iter().enumerate() {
+        let want = model.apply(*op);
+        let got = match *op {
+            Op::Fulfill(i) => lat.fulfill(key(i)),
+            Op::Complete(i) => lat.update_value(
+                key(i),
+                Item {
+                    id: key(i),
+                    completed: true,
+                },
+            ),
+        };
+
+        if want.is_ok() != got.is_ok() {
+            return Err(format!(
+                "step {} {:?}: model returned {:?}, lattice returned {:?}",
+                step, op, want, got
+            ));
+        }
+
+        compare(&model, &lat).map_err(|e| format!("step {} {:?}: {}", step, op, e))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use lattice_machines::calendar::{Calendar, Date, Schedule, Task};
+use lattice_machines::{BasicLattice, BasicNode, LatMachine, NodeType, ReadNode};
+
+// how many times each lattice is rebuilt, so a result that hangs on
+// HashMap iteration order shows up.
+const RUNS: usize = 50;
+
+#[derive(Clone, Debug)]
+struct Job {
+    id: String,
+    days: u32,
+}
+
+impl NodeType for Job {
+    fn uuid(&self) -> String {
+        self.id.clone()
+    }
+
+    fn is_completed(&self) -> bool {
+        false
+    }
+}
+
+fn job(id: &str, days: u32, depends_on: &[&str]) -> BasicNode<Job> {
+    BasicNode::new(
+        Job {
+            id: id.to_string(),
+            days,
+        },
+        depends_on.iter().map(|d| d.to_string()).collect(),
+        vec![],
+    )
+}
+
+fn plan(l: &BasicLattice<BasicNode<Job>>) -> Schedule {
+    // every day a working day, so dates are plain arithmetic.
+    let mut calendar = Calendar::new();
+    calendar.weekend = [false; 7];
+    l.schedule_dates(start(), &calendar, None, |_, t| Task {
+        days: t.base_data().days,
+        owner: None,
+    })
+}
+
+fn start() -> Date {
+    Date::from_ymd(2026, 1, 5).unwrap()
+}
+
+fn earliest_start(s: &Schedule, key: &str) -> Date {
+    s.windows
+        .iter()
+        .find(|w| w.key == key)
+        .unwrap()
+        .earliest_start
+}
+
+#[test]
+fn soft_dependencies_never_hold_back_a_start() {
+    for _ in 0..RUNS {
+        let mut l = BasicLattice::from_node_list(vec![
+            job("h", 1, &[]),
+            job("s", 10, &[]),
+            job("r", 1, &["h"]),
+        ])
+        .unwrap();
+        l.add_soft_requirement("r".to_string(), "s".to_string())
+            .unwrap();
+
+        let s = plan(&l);
+        assert_eq!(earliest_start(&s, "r"), start().add_days(1));
+    }
+}
+
+#[test]
+fn a_quorum_starts_after_its_earliest_members() {
+    for _ in 0..RUNS {
+        let mut l = BasicLattice::from_node_list(vec![
+            job("a", 10, &[]),
+            job("b", 1, &[]),
+            job("c", 3, &[]),
+            job("q", 1, &[]),
+        ])
+        .unwrap();
+        l.add_quorum(
+            "q".to_string(),
+            vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            2,
+        )
+        .unwrap();
+
+        let s = plan(&l);
+        assert_eq!(earliest_start(&s, "q"), start().add_days(3));
+    }
+}